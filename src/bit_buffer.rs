@@ -0,0 +1,94 @@
+use alloc::vec::Vec;
+
+/// A sequence of bits packed into bytes, most significant bit first
+///
+/// The encoded data is built up in a `BitBuffer` and then read as codewords, which are its
+/// bytes. The bits past the length in the last byte are zero.
+pub(crate) struct BitBuffer {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitBuffer {
+    /// Create an empty buffer
+    pub(crate) fn new() -> BitBuffer {
+        BitBuffer {
+            bytes: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Create an empty buffer with room for the number of bits
+    pub(crate) fn with_capacity(bits: usize) -> BitBuffer {
+        BitBuffer {
+            bytes: Vec::with_capacity(bits.div_ceil(8)),
+            len: 0,
+        }
+    }
+
+    /// Remove every bit, keeping the allocated bytes
+    pub(crate) fn clear(&mut self) {
+        self.bytes.clear();
+        self.len = 0;
+    }
+
+    /// Get the number of bits
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Get the bit at the index
+    pub(crate) fn get(&self, index: usize) -> bool {
+        self.bytes[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Append a bit
+    pub(crate) fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Append the `count` lowest bits of the value, most significant first
+    pub(crate) fn append_bits(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            self.push((value >> i) & 1 == 1);
+        }
+    }
+
+    /// Append a whole byte
+    pub(crate) fn append_byte(&mut self, byte: u8) {
+        match self.len % 8 {
+            0 => self.bytes.push(byte),
+            offset => {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= byte >> offset;
+                self.bytes.push(byte << (8 - offset));
+            }
+        }
+        self.len += 8;
+    }
+
+    /// Append the bits of another buffer
+    pub(crate) fn append(&mut self, other: &BitBuffer) {
+        let full = other.len / 8;
+        for &byte in &other.bytes[..full] {
+            self.append_byte(byte);
+        }
+        if !other.len.is_multiple_of(8) {
+            self.append_bits(
+                (other.bytes[full] >> (8 - other.len % 8)) as u32,
+                other.len % 8,
+            );
+        }
+    }
+
+    /// Get the bytes, where the last one is padded with zeros
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    encode,
+    mode_selector::{self, Input},
+    utils, ErrorCorrection, Mode, QRError, Version,
+};
+
+/// The data capacity of a version at an error correction level.
+///
+/// The character counts are those of data in a single mode, such as the limit of a form field
+/// that only accepts digits. Data mixing modes is measured with `QRBuilder::fit`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use qrforge::{Capacity, ErrorCorrection, QRError, Version};
+/// # fn main() -> Result<(), QRError> {
+/// let capacity = Capacity::new(Version::V(1), ErrorCorrection::L)?;
+/// assert_eq!(capacity.data_bits(), 152);
+/// assert_eq!(capacity.numeric(), 41);
+/// assert_eq!(capacity.alphanumeric(), 25);
+/// assert_eq!(capacity.byte(), 17);
+/// assert_eq!(capacity.kanji(), 10);
+///
+/// let capacity = Capacity::new(Version::M(1), ErrorCorrection::DetectionOnly)?;
+/// assert_eq!((capacity.numeric(), capacity.alphanumeric()), (5, 0));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Capacity {
+    version: usize,
+    error_correction: ErrorCorrection,
+    data_bits: usize,
+}
+
+impl Capacity {
+    /// Creates the capacity of the version at the error correction level.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the version is invalid or if it does not support the error
+    /// correction level.
+    pub fn new(version: Version, error_correction: ErrorCorrection) -> Result<Capacity, QRError> {
+        let version = utils::version_number(&version)?;
+
+        utils::check_error_correction(version, &error_correction)?;
+
+        let data_bits = utils::get_available_data_size(version, &error_correction)? as usize;
+
+        Ok(Capacity {
+            version,
+            error_correction,
+            data_bits,
+        })
+    }
+
+    /// Returns the version.
+    pub fn version(&self) -> Version {
+        utils::version_from_number(self.version)
+    }
+
+    /// Returns the error correction level.
+    pub fn error_correction(&self) -> ErrorCorrection {
+        self.error_correction.clone()
+    }
+
+    /// Returns the number of data bits, which hold the segments and the terminator.
+    pub fn data_bits(&self) -> usize {
+        self.data_bits
+    }
+
+    /// Returns the number of digits that fit.
+    pub fn numeric(&self) -> usize {
+        self.characters(&Mode::Numeric)
+    }
+
+    /// Returns the number of alphanumeric characters that fit, or 0 if the version does not
+    /// support alphanumeric mode.
+    pub fn alphanumeric(&self) -> usize {
+        self.characters(&Mode::Alphanumeric)
+    }
+
+    /// Returns the number of bytes that fit, or 0 if the version does not support byte mode.
+    pub fn byte(&self) -> usize {
+        self.characters(&Mode::Byte)
+    }
+
+    /// Returns the number of kanji characters that fit, or 0 if the version does not support
+    /// kanji mode.
+    pub fn kanji(&self) -> usize {
+        self.characters(&Mode::Kanji)
+    }
+
+    /// Returns the number of hanzi characters that fit, or 0 if the version does not support
+    /// hanzi mode.
+    pub fn hanzi(&self) -> usize {
+        self.characters(&Mode::Hanzi)
+    }
+
+    /// Get the largest number of characters of the mode that fit in the data bits
+    fn characters(&self, mode: &Mode) -> usize {
+        if mode_selector::check_mode(mode, self.version).is_err() {
+            return 0;
+        }
+
+        // The number of bits only grows with the number of characters
+        let (mut low, mut high) = (0, self.data_bits);
+        while low < high {
+            let count = (low + high).div_ceil(2);
+            match segment_bits(self.version, mode, count) {
+                Ok(bits) if bits <= self.data_bits => low = count,
+                _ => high = count - 1,
+            }
+        }
+
+        low
+    }
+}
+
+/// How the data of a builder fits in the version selected for it.
+///
+/// # Examples
+///
+/// ```rust
+/// # use qrforge::{ErrorCorrection, QRCode, QRError, Version};
+/// # fn main() -> Result<(), QRError> {
+/// let fit = QRCode::builder()
+///     .add_segment(None, b"HELLO WORLD")
+///     .fit(ErrorCorrection::Q)?;
+///
+/// assert!(matches!(fit.version(), Version::V(1)));
+/// assert_eq!(fit.used_bits(), 4 + 9 + 61);
+/// assert_eq!(fit.remaining_bits(), 104 - 74);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Fit {
+    version: usize,
+    error_correction: ErrorCorrection,
+    used_bits: usize,
+    data_bits: usize,
+}
+
+impl Fit {
+    /// Measure the data in the version at the error correction level
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the version does not support the error correction level or a
+    /// mode of the data, or if the data does not fit.
+    pub(crate) fn new(
+        inputs: &[Input],
+        version: &Version,
+        error_correction: ErrorCorrection,
+    ) -> Result<Fit, QRError> {
+        let version = utils::version_number(version)?;
+
+        utils::check_error_correction(version, &error_correction)?;
+
+        let used_bits = mode_selector::data_size(inputs, version)?;
+        let data_bits = utils::get_available_data_size(version, &error_correction)? as usize;
+
+        if used_bits > data_bits {
+            return Err(QRError::DataTooLarge {
+                required_bits: used_bits,
+                available_bits: data_bits,
+                version: utils::version_from_number(version),
+                required_version: None,
+            });
+        }
+
+        Ok(Fit {
+            version,
+            error_correction,
+            used_bits,
+            data_bits,
+        })
+    }
+
+    /// Returns the version.
+    pub fn version(&self) -> Version {
+        utils::version_from_number(self.version)
+    }
+
+    /// Returns the error correction level.
+    pub fn error_correction(&self) -> ErrorCorrection {
+        self.error_correction.clone()
+    }
+
+    /// Returns the number of bits of the encoded segments, without the terminator.
+    pub fn used_bits(&self) -> usize {
+        self.used_bits
+    }
+
+    /// Returns the number of data bits of the version at the error correction level.
+    pub fn data_bits(&self) -> usize {
+        self.data_bits
+    }
+
+    /// Returns the number of data bits left over, which are filled with the terminator and
+    /// padding.
+    pub fn remaining_bits(&self) -> usize {
+        self.data_bits - self.used_bits
+    }
+}
+
+/// Get the number of bits of a segment of `count` characters of the mode
+///
+/// A segment with more characters than the character count indicator holds is encoded as
+/// several segments, as `encode::encode_segment` does.
+fn segment_bits(version: usize, mode: &Mode, count: usize) -> Result<usize, QRError> {
+    let count_bits = encode::get_bit_count_for_length(version, mode)?;
+    let header = encode::get_mode(mode, version)?.len() + count_bits as usize;
+    let bytes_per_character = match mode {
+        Mode::Kanji | Mode::Hanzi => 2,
+        _ => 1,
+    };
+
+    let max = (1 << count_bits) - 1;
+    let full = header + utils::num_of_bits(mode, max * bytes_per_character);
+    let rest = match count % max {
+        0 => 0,
+        rest => header + utils::num_of_bits(mode, rest * bytes_per_character),
+    };
+
+    Ok(count / max * full + rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the numeric, alphanumeric, byte and kanji capacities of the version at each level
+    fn check(version: Version, levels: &[(ErrorCorrection, [usize; 4])]) {
+        for (error_correction, expected) in levels {
+            let capacity = Capacity::new(version.clone(), error_correction.clone()).unwrap();
+            let counts = [
+                capacity.numeric(),
+                capacity.alphanumeric(),
+                capacity.byte(),
+                capacity.kanji(),
+            ];
+            assert_eq!(
+                counts,
+                *expected,
+                "{} level {}",
+                version,
+                error_correction.to_value()
+            );
+        }
+    }
+
+    #[test]
+    fn capacities_match_the_iso_tables() {
+        use ErrorCorrection::*;
+
+        check(
+            Version::V(1),
+            &[
+                (L, [41, 25, 17, 10]),
+                (M, [34, 20, 14, 8]),
+                (Q, [27, 16, 11, 7]),
+                (H, [17, 10, 7, 4]),
+            ],
+        );
+        check(
+            Version::V(10),
+            &[
+                (L, [652, 395, 271, 167]),
+                (M, [513, 311, 213, 131]),
+                (Q, [364, 221, 151, 93]),
+                (H, [288, 174, 119, 74]),
+            ],
+        );
+        check(
+            Version::V(40),
+            &[
+                (L, [7089, 4296, 2953, 1817]),
+                (M, [5596, 3391, 2331, 1435]),
+                (Q, [3993, 2420, 1663, 1024]),
+                (H, [3057, 1852, 1273, 784]),
+            ],
+        );
+
+        check(Version::M(1), &[(DetectionOnly, [5, 0, 0, 0])]);
+        check(Version::M(2), &[(L, [10, 6, 0, 0]), (M, [8, 5, 0, 0])]);
+        check(Version::M(3), &[(L, [23, 14, 9, 6]), (M, [18, 11, 7, 4])]);
+        check(
+            Version::M(4),
+            &[
+                (L, [35, 21, 15, 9]),
+                (M, [30, 18, 13, 8]),
+                (Q, [21, 13, 9, 5]),
+            ],
+        );
+
+        check(Version::R(7, 43), &[(M, [12, 7, 5, 3]), (H, [5, 3, 2, 1])]);
+    }
+}
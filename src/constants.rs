@@ -1,4 +1,4 @@
-pub(crate) const BLOCK_LOOKUP: [[[u32; 4]; 4]; 44] = [
+pub(crate) const BLOCK_LOOKUP: [[[u32; 4]; 4]; 76] = [
     // Version 1
     [[1, 19, 0, 0], [1, 16, 0, 0], [1, 13, 0, 0], [1, 9, 0, 0]],
     // Version 2
@@ -135,14 +135,14 @@ pub(crate) const BLOCK_LOOKUP: [[[u32; 4]; 4]; 44] = [
         [6, 117, 4, 118],
         [6, 45, 14, 46],
         [11, 24, 16, 25],
-        [30, 16, 0, 0],
+        [30, 16, 2, 17],
     ],
     // Version 25
     [
         [8, 106, 4, 107],
         [8, 47, 13, 48],
         [7, 24, 22, 25],
-        [22, 16, 8, 17],
+        [22, 15, 13, 16],
     ],
     // Version 26
     [
@@ -257,9 +257,73 @@ pub(crate) const BLOCK_LOOKUP: [[[u32; 4]; 4]; 44] = [
     [[1, 11, 0, 0], [1, 9, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]],
     // Version Micro 4
     [[1, 16, 0, 0], [1, 14, 0, 0], [1, 10, 0, 0], [0, 0, 0, 0]],
+    // rMQR R7x43
+    [[0, 0, 0, 0], [1, 6, 0, 0], [0, 0, 0, 0], [1, 3, 0, 0]],
+    // rMQR R7x59
+    [[0, 0, 0, 0], [1, 12, 0, 0], [0, 0, 0, 0], [1, 7, 0, 0]],
+    // rMQR R7x77
+    [[0, 0, 0, 0], [1, 20, 0, 0], [0, 0, 0, 0], [1, 10, 0, 0]],
+    // rMQR R7x99
+    [[0, 0, 0, 0], [1, 28, 0, 0], [0, 0, 0, 0], [1, 14, 0, 0]],
+    // rMQR R7x139
+    [[0, 0, 0, 0], [1, 44, 0, 0], [0, 0, 0, 0], [2, 12, 0, 0]],
+    // rMQR R9x43
+    [[0, 0, 0, 0], [1, 12, 0, 0], [0, 0, 0, 0], [1, 7, 0, 0]],
+    // rMQR R9x59
+    [[0, 0, 0, 0], [1, 21, 0, 0], [0, 0, 0, 0], [1, 11, 0, 0]],
+    // rMQR R9x77
+    [[0, 0, 0, 0], [1, 31, 0, 0], [0, 0, 0, 0], [1, 8, 1, 9]],
+    // rMQR R9x99
+    [[0, 0, 0, 0], [1, 42, 0, 0], [0, 0, 0, 0], [2, 11, 0, 0]],
+    // rMQR R9x139
+    [[0, 0, 0, 0], [1, 31, 1, 32], [0, 0, 0, 0], [3, 11, 0, 0]],
+    // rMQR R11x27
+    [[0, 0, 0, 0], [1, 7, 0, 0], [0, 0, 0, 0], [1, 5, 0, 0]],
+    // rMQR R11x43
+    [[0, 0, 0, 0], [1, 19, 0, 0], [0, 0, 0, 0], [1, 11, 0, 0]],
+    // rMQR R11x59
+    [[0, 0, 0, 0], [1, 31, 0, 0], [0, 0, 0, 0], [1, 7, 1, 8]],
+    // rMQR R11x77
+    [[0, 0, 0, 0], [1, 43, 0, 0], [0, 0, 0, 0], [1, 11, 1, 12]],
+    // rMQR R11x99
+    [[0, 0, 0, 0], [1, 28, 1, 29], [0, 0, 0, 0], [1, 14, 1, 15]],
+    // rMQR R11x139
+    [[0, 0, 0, 0], [3, 28, 0, 0], [0, 0, 0, 0], [3, 14, 0, 0]],
+    // rMQR R13x27
+    [[0, 0, 0, 0], [1, 12, 0, 0], [0, 0, 0, 0], [1, 7, 0, 0]],
+    // rMQR R13x43
+    [[0, 0, 0, 0], [1, 27, 0, 0], [0, 0, 0, 0], [1, 13, 0, 0]],
+    // rMQR R13x59
+    [[0, 0, 0, 0], [1, 38, 0, 0], [0, 0, 0, 0], [2, 10, 0, 0]],
+    // rMQR R13x77
+    [[0, 0, 0, 0], [1, 26, 1, 27], [0, 0, 0, 0], [1, 14, 1, 15]],
+    // rMQR R13x99
+    [[0, 0, 0, 0], [1, 36, 1, 37], [0, 0, 0, 0], [1, 11, 2, 12]],
+    // rMQR R13x139
+    [[0, 0, 0, 0], [2, 27, 2, 28], [0, 0, 0, 0], [2, 13, 2, 14]],
+    // rMQR R15x43
+    [[0, 0, 0, 0], [1, 33, 0, 0], [0, 0, 0, 0], [1, 7, 1, 8]],
+    // rMQR R15x59
+    [[0, 0, 0, 0], [1, 48, 0, 0], [0, 0, 0, 0], [2, 13, 0, 0]],
+    // rMQR R15x77
+    [[0, 0, 0, 0], [1, 33, 1, 34], [0, 0, 0, 0], [2, 10, 1, 11]],
+    // rMQR R15x99
+    [[0, 0, 0, 0], [2, 44, 0, 0], [0, 0, 0, 0], [4, 12, 0, 0]],
+    // rMQR R15x139
+    [[0, 0, 0, 0], [2, 42, 1, 43], [0, 0, 0, 0], [1, 13, 4, 14]],
+    // rMQR R17x43
+    [[0, 0, 0, 0], [1, 39, 0, 0], [0, 0, 0, 0], [1, 10, 1, 11]],
+    // rMQR R17x59
+    [[0, 0, 0, 0], [2, 28, 0, 0], [0, 0, 0, 0], [2, 14, 0, 0]],
+    // rMQR R17x77
+    [[0, 0, 0, 0], [2, 39, 0, 0], [0, 0, 0, 0], [1, 12, 2, 13]],
+    // rMQR R17x99
+    [[0, 0, 0, 0], [2, 35, 1, 36], [0, 0, 0, 0], [4, 14, 0, 0]],
+    // rMQR R17x139
+    [[0, 0, 0, 0], [4, 38, 0, 0], [0, 0, 0, 0], [2, 12, 4, 13]],
 ];
 
-pub(crate) const EC_CODEWORDS: [[usize; 4]; 44] = [
+pub(crate) const EC_CODEWORDS: [[usize; 4]; 76] = [
     // Version 1-10
     [7, 10, 13, 17],
     [10, 16, 22, 28],
@@ -309,9 +373,42 @@ pub(crate) const EC_CODEWORDS: [[usize; 4]; 44] = [
     [5, 6, 0, 0],
     [6, 8, 0, 0],
     [8, 10, 14, 0],
+    // rMQR R7x43 to R17x139
+    [0, 7, 0, 10],
+    [0, 9, 0, 14],
+    [0, 12, 0, 22],
+    [0, 16, 0, 30],
+    [0, 24, 0, 22],
+    [0, 9, 0, 14],
+    [0, 12, 0, 22],
+    [0, 18, 0, 16],
+    [0, 24, 0, 22],
+    [0, 18, 0, 22],
+    [0, 8, 0, 10],
+    [0, 12, 0, 20],
+    [0, 16, 0, 16],
+    [0, 24, 0, 22],
+    [0, 16, 0, 30],
+    [0, 16, 0, 30],
+    [0, 9, 0, 14],
+    [0, 14, 0, 28],
+    [0, 22, 0, 20],
+    [0, 16, 0, 28],
+    [0, 20, 0, 26],
+    [0, 14, 0, 28],
+    [0, 18, 0, 18],
+    [0, 26, 0, 24],
+    [0, 18, 0, 24],
+    [0, 24, 0, 22],
+    [0, 24, 0, 26],
+    [0, 22, 0, 20],
+    [0, 16, 0, 30],
+    [0, 22, 0, 28],
+    [0, 18, 0, 26],
+    [0, 20, 0, 26],
 ];
 
-pub(crate) const DATA_CODEWORDS: [[u32; 4]; 44] = [
+pub(crate) const DATA_CODEWORDS: [[u32; 4]; 76] = [
     [19 * 8, 16 * 8, 13 * 8, 9 * 8],          // Version 1
     [34 * 8, 28 * 8, 22 * 8, 16 * 8],         // Version 2
     [55 * 8, 44 * 8, 34 * 8, 26 * 8],         // Version 3
@@ -357,6 +454,39 @@ pub(crate) const DATA_CODEWORDS: [[u32; 4]; 44] = [
     [40, 32, 0, 0],    // micro v2
     [84, 68, 0, 0],    // micro v3
     [128, 112, 80, 0], // micro v4
+    // rMQR versions
+    [0, 6 * 8, 0, 3 * 8],    // rMQR R7x43
+    [0, 12 * 8, 0, 7 * 8],   // rMQR R7x59
+    [0, 20 * 8, 0, 10 * 8],  // rMQR R7x77
+    [0, 28 * 8, 0, 14 * 8],  // rMQR R7x99
+    [0, 44 * 8, 0, 24 * 8],  // rMQR R7x139
+    [0, 12 * 8, 0, 7 * 8],   // rMQR R9x43
+    [0, 21 * 8, 0, 11 * 8],  // rMQR R9x59
+    [0, 31 * 8, 0, 17 * 8],  // rMQR R9x77
+    [0, 42 * 8, 0, 22 * 8],  // rMQR R9x99
+    [0, 63 * 8, 0, 33 * 8],  // rMQR R9x139
+    [0, 7 * 8, 0, 5 * 8],    // rMQR R11x27
+    [0, 19 * 8, 0, 11 * 8],  // rMQR R11x43
+    [0, 31 * 8, 0, 15 * 8],  // rMQR R11x59
+    [0, 43 * 8, 0, 23 * 8],  // rMQR R11x77
+    [0, 57 * 8, 0, 29 * 8],  // rMQR R11x99
+    [0, 84 * 8, 0, 42 * 8],  // rMQR R11x139
+    [0, 12 * 8, 0, 7 * 8],   // rMQR R13x27
+    [0, 27 * 8, 0, 13 * 8],  // rMQR R13x43
+    [0, 38 * 8, 0, 20 * 8],  // rMQR R13x59
+    [0, 53 * 8, 0, 29 * 8],  // rMQR R13x77
+    [0, 73 * 8, 0, 35 * 8],  // rMQR R13x99
+    [0, 110 * 8, 0, 54 * 8], // rMQR R13x139
+    [0, 33 * 8, 0, 15 * 8],  // rMQR R15x43
+    [0, 48 * 8, 0, 26 * 8],  // rMQR R15x59
+    [0, 67 * 8, 0, 31 * 8],  // rMQR R15x77
+    [0, 88 * 8, 0, 48 * 8],  // rMQR R15x99
+    [0, 127 * 8, 0, 69 * 8], // rMQR R15x139
+    [0, 39 * 8, 0, 21 * 8],  // rMQR R17x43
+    [0, 56 * 8, 0, 28 * 8],  // rMQR R17x59
+    [0, 78 * 8, 0, 38 * 8],  // rMQR R17x77
+    [0, 106 * 8, 0, 56 * 8], // rMQR R17x99
+    [0, 152 * 8, 0, 76 * 8], // rMQR R17x139
 ];
 
 pub(crate) const ALPHANUMERIC: [char; 45] = [
@@ -392,6 +522,92 @@ pub(crate) const FORMAT_INFO_MICRO: [u16; 32] = [
 pub(crate) const MICRO_MAPPING: [[u32; 4]; 4] =
     [[0, 0, 0, 0], [1, 2, 0, 0], [3, 4, 0, 0], [5, 6, 7, 0]];
 
+/// Heights and widths of the rMQR versions, in the order of their version indicator
+pub(crate) const RMQR_SIZES: [(usize, usize); 32] = [
+    (7, 43),
+    (7, 59),
+    (7, 77),
+    (7, 99),
+    (7, 139),
+    (9, 43),
+    (9, 59),
+    (9, 77),
+    (9, 99),
+    (9, 139),
+    (11, 27),
+    (11, 43),
+    (11, 59),
+    (11, 77),
+    (11, 99),
+    (11, 139),
+    (13, 27),
+    (13, 43),
+    (13, 59),
+    (13, 77),
+    (13, 99),
+    (13, 139),
+    (15, 43),
+    (15, 59),
+    (15, 77),
+    (15, 99),
+    (15, 139),
+    (17, 43),
+    (17, 59),
+    (17, 77),
+    (17, 99),
+    (17, 139),
+];
+
+/// Bits of the character count indicator of the rMQR versions for numeric, alphanumeric, byte
+/// and kanji mode
+pub(crate) const RMQR_COUNT_BITS: [[u32; 4]; 32] = [
+    [4, 3, 3, 2], // R7x43
+    [5, 5, 4, 3], // R7x59
+    [6, 5, 5, 4], // R7x77
+    [7, 6, 5, 5], // R7x99
+    [7, 6, 6, 5], // R7x139
+    [5, 5, 4, 3], // R9x43
+    [6, 5, 5, 4], // R9x59
+    [7, 6, 5, 5], // R9x77
+    [7, 6, 6, 5], // R9x99
+    [8, 7, 6, 6], // R9x139
+    [4, 4, 3, 2], // R11x27
+    [6, 5, 5, 4], // R11x43
+    [7, 6, 5, 5], // R11x59
+    [7, 6, 6, 5], // R11x77
+    [8, 7, 6, 6], // R11x99
+    [8, 7, 7, 6], // R11x139
+    [5, 5, 4, 3], // R13x27
+    [6, 6, 5, 5], // R13x43
+    [7, 6, 6, 5], // R13x59
+    [7, 7, 6, 6], // R13x77
+    [8, 7, 7, 6], // R13x99
+    [8, 8, 7, 7], // R13x139
+    [7, 6, 6, 5], // R15x43
+    [7, 7, 6, 5], // R15x59
+    [8, 7, 7, 6], // R15x77
+    [8, 7, 7, 6], // R15x99
+    [9, 8, 7, 7], // R15x139
+    [7, 6, 6, 5], // R17x43
+    [8, 7, 6, 6], // R17x59
+    [8, 7, 7, 6], // R17x77
+    [8, 8, 7, 6], // R17x99
+    [9, 8, 8, 7], // R17x139
+];
+
+/// Columns of the rMQR alignment patterns for each width
+pub(crate) const RMQR_ALIGNMENT_PATTERN_LOCATION: [(usize, &[usize]); 6] = [
+    (27, &[]),
+    (43, &[21]),
+    (59, &[19, 39]),
+    (77, &[25, 51]),
+    (99, &[23, 49, 75]),
+    (139, &[27, 55, 83, 111]),
+];
+
+/// Masks of the rMQR format information next to the finder pattern and the finder sub pattern
+pub(crate) const RMQR_FORMAT_INFO_MASKS: [u32; 2] = [0b011111101010110010, 0b100000101001111011];
+
 pub(crate) const ALIGNMENT_PATTERN_LOCATION: [&[usize]; 39] = [
     &[6, 18],
     &[6, 22],
@@ -442,23 +658,19 @@ pub(crate) const FORMAT_INFORMATION: [u16; 32] = [
 
 pub(crate) const VERSION_INFORMATION: [u32; 34] = [
     0x07C94, 0x085BC, 0x09A99, 0x0A4D3, 0x0BBF6, 0x0C762, 0x0D847, 0x0E60D, 0x0F928, 0x10B78,
-    0x1145D, 0x12A17, 0x13532, 0x149A6, 0x15683, 0x168C9, 0x177EC, 0x18EC4, 0x19D03, 0x1AFAB,
-    0x1B08E, 0x1CC1A, 0x1D33F, 0x1ED75, 0x1F250, 0x209D5, 0x216C3, 0x228BA, 0x2379F, 0x24B0B,
+    0x1145D, 0x12A17, 0x13532, 0x149A6, 0x15683, 0x168C9, 0x177EC, 0x18EC4, 0x191E1, 0x1AFAB,
+    0x1B08E, 0x1CC1A, 0x1D33F, 0x1ED75, 0x1F250, 0x209D5, 0x216F0, 0x228BA, 0x2379F, 0x24B0B,
     0x2542E, 0x26A64, 0x27541, 0x28C69,
 ];
 
-pub(crate) const REMAINING_BITS: [u32; 44] = [
+pub(crate) const REMAINING_BITS: [u32; 76] = [
     0, 7, 7, 7, 7, 7, 0, 0, 0, 0, 0, 0, 0, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 3, 3, 3, 3, 3,
-    3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-];
-
-// remaining bits 11101100 00010001
-pub(crate) const REMAINDER_BITS: [[bool; 8]; 2] = [
-    [true, true, true, false, true, true, false, false],
-    [false, false, false, true, false, false, false, true],
+    3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 5, 6, 1, 2, 3, 1, 4, 5, 2, 1, 0, 2, 7, 6, 4, 1, 6, 4,
+    3, 0, 1, 4, 6, 7, 2, 1, 2, 0, 3, 4,
 ];
 
-pub(crate) const PATTERN: [bool; 7] = [true, false, true, true, true, false, true];
+/// The pad codewords 11101100 and 00010001, which alternately fill the unused data codewords
+pub(crate) const PAD_CODEWORDS: [u8; 2] = [0xEC, 0x11];
 
 pub(crate) const ANTILOG_TABLE: [u8; 256] = [
     1, 2, 4, 8, 16, 32, 64, 128, 29, 58, 116, 232, 205, 135, 19, 38, 76, 152, 45, 90, 180, 117,
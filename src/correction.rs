@@ -1,197 +1,138 @@
+use alloc::vec::Vec;
+
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
 use crate::{
-    constants::{self, BLOCK_LOOKUP, EC_CODEWORDS},
-    ErrorCorrection,
+    constants::{ANTILOG_TABLE, BLOCK_LOOKUP, EC_CODEWORDS, LOG_TABLE},
+    utils, ErrorCorrection,
 };
 
-/// A block of data
-type Block = Vec<Vec<bool>>;
-/// A block of error correction data
-type ECBlock = Vec<Vec<bool>>;
-
-/// Perform error correction on the data
+/// A block of data codewords
+type Block = Vec<u8>;
+/// A block of error correction codewords
+type ECBlock = Vec<u8>;
+
+/// Largest number of error correction codewords of a block
+const MAX_EC_CODEWORDS: usize = 30;
+
+/// The generator polynomials of every degree up to `MAX_EC_CODEWORDS`
+///
+/// The generator polynomial of degree n is the product of (x - α^i) for i from 0 to n - 1.
+/// Its coefficients are stored from x^(n-1) down to x^0, without the leading coefficient of
+/// x^n, which is 1.
+const GENERATOR_POLYNOMIALS: [[u8; MAX_EC_CODEWORDS]; MAX_EC_CODEWORDS + 1] =
+    generator_polynomials();
+
+/// Perform error correction on the data codewords
+///
+/// The data codewords are split into `blocks` and the error correction codewords of each block
+/// are computed into `ec_blocks`, reusing the blocks the buffers already hold.
 pub(crate) fn correction(
     version: usize,
     error_correction: &ErrorCorrection,
-    combined_data: Vec<bool>,
-) -> (Vec<Block>, Vec<ECBlock>) {
-    let blocks: Vec<Block> = split_into_blocks(combined_data, version, error_correction);
+    combined_data: &[u8],
+    blocks: &mut Vec<Block>,
+    ec_blocks: &mut Vec<ECBlock>,
+) {
+    split_into_blocks(combined_data, version, error_correction, blocks);
     let ec_codewords = ec_codewords(version, error_correction);
+    let generator = &GENERATOR_POLYNOMIALS[ec_codewords][..ec_codewords];
 
-    // Pre-generate generator polynomial once
-    let generator = generate_generator_polynomial(ec_codewords);
+    ec_blocks.resize_with(blocks.len(), Vec::new);
 
     // Process blocks in parallel
     #[cfg(feature = "parallel")]
-    let ec_blocks: Vec<ECBlock> = blocks
+    blocks
         .par_iter()
-        .map(|block| {
-            let polynomial = build_polynomial(block);
-            let result = part0(ec_codewords, &generator, &polynomial);
-
-            // Pre-allocate result vectors
-            result
-                .par_iter()
-                .map(|&code| {
-                    let mut data = Vec::with_capacity(8);
-                    (0..8).for_each(|k| {
-                        data.push((code & (1 << (7 - k))) != 0);
-                    });
-                    data
-                })
-                .collect()
-        })
-        .collect();
+        .zip(ec_blocks.par_iter_mut())
+        .for_each(|(block, ec_block)| remainder(block, generator, ec_block));
 
     // Process blocks sequentially
     #[cfg(not(feature = "parallel"))]
-    let ec_blocks: Vec<ECBlock> = blocks
+    blocks
         .iter()
-        .map(|block| {
-            let polynomial = build_polynomial(block);
-            let result = part0(ec_codewords, &generator, &polynomial);
-
-            // Pre-allocate result vectors
-            result
-                .iter()
-                .map(|&code| {
-                    let mut data = Vec::with_capacity(8);
-                    (0..8).for_each(|k| {
-                        data.push((code & (1 << (7 - k))) != 0);
-                    });
-                    data
-                })
-                .collect()
-        })
-        .collect();
-
-    (blocks, ec_blocks)
-}
-
-/// Build polynomial from data
-fn build_polynomial(data: &[Vec<bool>]) -> Vec<(u32, u32)> {
-    let mut polynomial = Vec::with_capacity(data.len());
-    let size = (data.len() - 1) as u32;
-
-    for (i, byte) in data.iter().enumerate() {
-        let value = byte.iter().enumerate().fold(0, |acc, (j, &bit)| {
-            acc + if bit { 2u32.pow(7 - j as u32) } else { 0 }
-        });
-        polynomial.push((value, size - i as u32));
-    }
-    polynomial
-}
-
-/// Generate generator polynomial
-fn generate_generator_polynomial(ec_codewords: u32) -> Vec<(u32, u32)> {
-    // Start with X^1
-    let mut polynomial = vec![(0, 1)]; // (coefficient α^0, exponent 1)
-
-    // Multiply by (X + α^i) ec_codewords times
-    for i in 0..ec_codewords {
-        polynomial = multiply_polynomial(&polynomial, i);
-    }
-
-    polynomial.sort_by(|a, b| b.1.cmp(&a.1));
-    polynomial
+        .zip(ec_blocks.iter_mut())
+        .for_each(|(block, ec_block)| remainder(block, generator, ec_block));
 }
 
-/// Multiply two polynomials
-fn multiply_polynomial(polynomial: &[(u32, u32)], alpha_power: u32) -> Vec<(u32, u32)> {
-    // Collect expanded terms
-    let mut result_temp = Vec::with_capacity(polynomial.len() * 2);
-    for &(coeff, exp) in polynomial {
-        // Multiply with (0, 1) => shift exponent by 1
-        result_temp.push((coeff % 255, exp + 1));
-        // Multiply with (alpha^alpha_power, 0)
-        result_temp.push(((coeff + alpha_power) % 255, exp));
-    }
+/// Compute the error correction codewords of a block into `remainder`
+///
+/// These are the remainder of the data polynomial times x^n divided by the generator
+/// polynomial of degree n, computed with a linear feedback shift register: every data codeword
+/// is added to the leading term of the remainder, which is then shifted out and the generator
+/// polynomial times it added to the rest.
+fn remainder(data: &[u8], generator: &[u8], remainder: &mut ECBlock) {
+    remainder.clear();
+    remainder.resize(generator.len(), 0);
+
+    for &codeword in data {
+        let factor = codeword ^ remainder[0];
+        remainder.rotate_left(1);
+        if let Some(last) = remainder.last_mut() {
+            *last = 0;
+        }
 
-    // Combine like exponents
-    let mut result = Vec::new();
-    for (val, e) in result_temp {
-        if let Some(existing) = result.iter_mut().find(|(_, ex)| *ex == e) {
-            let tmp = lookup(reverse_lookup(val) ^ reverse_lookup(existing.0));
-            existing.0 = tmp;
-        } else {
-            result.push((val, e));
+        if factor != 0 {
+            let log_factor = LOG_TABLE[factor as usize] as usize;
+            for (value, &coefficient) in remainder.iter_mut().zip(generator) {
+                if coefficient != 0 {
+                    let log = LOG_TABLE[coefficient as usize] as usize + log_factor;
+                    *value ^= ANTILOG_TABLE[log % 255];
+                }
+            }
         }
     }
-    result
 }
 
-/// first step of creating error correction codewords
-fn part0(n: u32, generator: &[(u32, u32)], data_polynomial: &[(u32, u32)]) -> Vec<u32> {
-    let mut polynomial: Vec<(u32, u32)> = Vec::new();
-    for (a, b) in data_polynomial.iter() {
-        polynomial.push((*a, (*b) + n));
-    }
-    let mut generator_polynomial: Vec<(u32, u32)> = Vec::new();
-    let diff = polynomial.first().unwrap().1 - generator.first().unwrap().1;
-
-    for (a, b) in generator.iter() {
-        generator_polynomial.push((*a, (*b) + diff));
+/// Multiply two elements of GF(256)
+const fn multiply(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
     }
 
-    partn(
-        &polynomial,
-        &generator_polynomial,
-        data_polynomial.len() as u32,
-    )
+    ANTILOG_TABLE[(LOG_TABLE[a as usize] as usize + LOG_TABLE[b as usize] as usize) % 255]
 }
 
-/// Recursive step of creating error correction codewords
-fn partn(polynomial: &[(u32, u32)], generator: &[(u32, u32)], n: u32) -> Vec<u32> {
-    if n == 0 {
-        return polynomial.iter().map(|(a, _)| *a).collect();
-    }
-
-    let lookup_value = lookup(polynomial[0].0);
-
-    // Pre-allocate new polynomial with max possible size
-    let mut new_poly = Vec::with_capacity(max(polynomial.len(), generator.len()));
-
-    // Combine generator transformation and XOR operations
-    for i in 1..max(polynomial.len(), generator.len()) {
-        let poly_val = polynomial.get(i).map_or(0, |&(a, _)| a);
-        let gen_val = if i < generator.len() {
-            reverse_lookup((generator[i].0 + lookup_value) % 255)
-        } else {
-            0
-        };
-
-        new_poly.push((poly_val ^ gen_val, polynomial[0].1 - i as u32));
-    }
-
-    partn(&new_poly, generator, n - 1)
-}
+/// Compute the generator polynomials of every degree up to `MAX_EC_CODEWORDS`
+const fn generator_polynomials() -> [[u8; MAX_EC_CODEWORDS]; MAX_EC_CODEWORDS + 1] {
+    let mut polynomials = [[0; MAX_EC_CODEWORDS]; MAX_EC_CODEWORDS + 1];
+
+    let mut degree = 1;
+    while degree <= MAX_EC_CODEWORDS {
+        // Start with the polynomial 1 and multiply it by (x - α^i) for every root α^i
+        let polynomial = &mut polynomials[degree];
+        polynomial[degree - 1] = 1;
+
+        let mut root = 1;
+        let mut i = 0;
+        while i < degree {
+            let mut j = 0;
+            while j < degree {
+                polynomial[j] = multiply(polynomial[j], root);
+                if j + 1 < degree {
+                    polynomial[j] ^= polynomial[j + 1];
+                }
+                j += 1;
+            }
+            root = multiply(root, 2);
+            i += 1;
+        }
 
-/// Find maximum of two usize values
-fn max(a: usize, b: usize) -> usize {
-    if a > b {
-        return a;
+        degree += 1;
     }
-    b
-}
-
-/// Perform a lookup in the log table
-fn lookup(a: u32) -> u32 {
-    constants::LOG_TABLE[a as usize] as u32
-}
 
-fn reverse_lookup(a: u32) -> u32 {
-    constants::ANTILOG_TABLE[a as usize] as u32
+    polynomials
 }
 
-/// Split data into correct sized blocks
+/// Split the data codewords into the blocks
 fn split_into_blocks(
-    combined_data: Vec<bool>,
+    combined_data: &[u8],
     version: usize,
     error_correction: &ErrorCorrection,
-) -> Vec<Vec<Vec<bool>>> {
-    let correction_level = error_correction.to_value();
+    blocks: &mut Vec<Block>,
+) {
+    let correction_level = utils::ec_index(error_correction);
 
     let block_lookup = BLOCK_LOOKUP[version - 1][correction_level];
 
@@ -200,26 +141,177 @@ fn split_into_blocks(
     let group2_blocks = block_lookup[2] as usize;
     let group2_amount = block_lookup[3] as usize;
 
-    let codewords: Vec<Vec<bool>> = combined_data.chunks(8).map(|c| c.to_vec()).collect();
+    let lengths = core::iter::repeat_n(group1_amount, group1_blocks)
+        .chain(core::iter::repeat_n(group2_amount, group2_blocks));
 
-    let mut blocks = Vec::new();
+    blocks.resize_with(group1_blocks + group2_blocks, Vec::new);
     let mut offset = 0;
 
-    for _ in 0..group1_blocks {
-        blocks.push(codewords[offset..offset + group1_amount].to_vec());
-        offset += group1_amount;
-    }
-    for _ in 0..group2_blocks {
-        blocks.push(codewords[offset..offset + group2_amount].to_vec());
-        offset += group2_amount;
+    for (block, length) in blocks.iter_mut().zip(lengths) {
+        block.clear();
+        block.extend_from_slice(&combined_data[offset..offset + length]);
+        offset += length;
     }
+}
 
-    blocks
+/// Get the number of error correction codewords of a block
+fn ec_codewords(version: usize, error_correction: &ErrorCorrection) -> usize {
+    let correction_level = utils::ec_index(error_correction);
+
+    EC_CODEWORDS[version - 1][correction_level]
 }
 
-/// Get the number of error correction codewords
-fn ec_codewords(version: usize, error_correction: &ErrorCorrection) -> u32 {
-    let correction_level = error_correction.to_value();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::DATA_CODEWORDS;
+    use alloc::vec;
+
+    /// Get the data and error correction blocks of the data codewords
+    fn blocks(
+        version: usize,
+        error_correction: &ErrorCorrection,
+        data: &[u8],
+    ) -> (Vec<Block>, Vec<ECBlock>) {
+        let (mut blocks, mut ec_blocks) = (vec![], vec![]);
+        correction(version, error_correction, data, &mut blocks, &mut ec_blocks);
+        (blocks, ec_blocks)
+    }
+
+    #[test]
+    fn blocks_hold_the_data_codewords() {
+        let levels = [
+            ErrorCorrection::L,
+            ErrorCorrection::M,
+            ErrorCorrection::Q,
+            ErrorCorrection::H,
+        ];
+
+        for version in 1..=40 {
+            for error_correction in &levels {
+                let [group1_blocks, group1_amount, group2_blocks, group2_amount] =
+                    BLOCK_LOOKUP[version - 1][error_correction.to_value()];
+
+                assert_eq!(
+                    (group1_blocks * group1_amount + group2_blocks * group2_amount) * 8,
+                    DATA_CODEWORDS[version - 1][error_correction.to_value()],
+                    "version {} level {}",
+                    version,
+                    error_correction.to_value()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_match_the_iso_table() {
+        // Table 9 of ISO/IEC 18004, the number of blocks and data codewords of each group
+        let expected = [
+            (1, ErrorCorrection::L, [1, 19, 0, 0]),
+            (5, ErrorCorrection::Q, [2, 15, 2, 16]),
+            (24, ErrorCorrection::H, [30, 16, 2, 17]),
+            (25, ErrorCorrection::H, [22, 15, 13, 16]),
+            (40, ErrorCorrection::H, [20, 15, 61, 16]),
+        ];
+
+        for (version, error_correction, blocks) in expected {
+            assert_eq!(
+                BLOCK_LOOKUP[version - 1][error_correction.to_value()],
+                blocks,
+                "version {}",
+                version
+            );
+        }
+    }
+
+    /// Multiply two elements of GF(256) by shifts and additions
+    fn reference_multiply(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0;
+        while b != 0 {
+            if b & 1 == 1 {
+                product ^= a;
+            }
+            a = (a << 1) ^ if a & 0x80 != 0 { 0x1D } else { 0 };
+            b >>= 1;
+        }
+        product
+    }
 
-    EC_CODEWORDS[version - 1][correction_level] as u32
+    /// Compute the error correction codewords by long division of the data polynomial times
+    /// x^n by the generator polynomial of degree n
+    fn reference_remainder(data: &[u8], n: usize) -> Vec<u8> {
+        let mut generator = vec![1];
+        let mut root = 1;
+        for _ in 0..n {
+            let mut product = vec![0; generator.len() + 1];
+            for (i, &coefficient) in generator.iter().enumerate() {
+                product[i] ^= coefficient;
+                product[i + 1] ^= reference_multiply(coefficient, root);
+            }
+            generator = product;
+            root = reference_multiply(root, 2);
+        }
+
+        let mut message = [data, &vec![0; n]].concat();
+        for i in 0..data.len() {
+            let factor = message[i];
+            for (j, &coefficient) in generator.iter().enumerate() {
+                message[i + j] ^= reference_multiply(coefficient, factor);
+            }
+        }
+        message.split_off(data.len())
+    }
+
+    #[test]
+    fn error_correction_matches_the_long_division() {
+        let levels = [
+            ErrorCorrection::DetectionOnly,
+            ErrorCorrection::L,
+            ErrorCorrection::M,
+            ErrorCorrection::Q,
+            ErrorCorrection::H,
+        ];
+
+        for version in 1..=76 {
+            for error_correction in &levels {
+                if utils::check_error_correction(version, error_correction).is_err() {
+                    continue;
+                }
+
+                let codewords = utils::get_available_data_size(version, error_correction)
+                    .unwrap()
+                    .div_ceil(8) as usize;
+                let data: Vec<u8> = (0..codewords)
+                    .map(|i| (i * 31 + version * 7) as u8)
+                    .collect();
+
+                let (blocks, ec_blocks) = blocks(version, error_correction, &data);
+                let n = ec_codewords(version, error_correction);
+                for (block, ec_block) in blocks.iter().zip(&ec_blocks) {
+                    assert_eq!(
+                        *ec_block,
+                        reference_remainder(block, n),
+                        "version {}",
+                        version
+                    );
+                }
+            }
+        }
+    }
+
+    // The recursive division that held the polynomials as exponent pairs got these wrong, as
+    // a leading coefficient of the remainder becomes zero during the division
+    #[test]
+    fn zero_leading_coefficients_are_divided() {
+        let data: Vec<u8> = (0..80).map(|i| (i * 31 + 28) as u8).collect();
+
+        let (_, ec_blocks) = blocks(4, &ErrorCorrection::L, &data);
+        assert_eq!(
+            ec_blocks,
+            [[
+                0x83, 0xE0, 0x3F, 0x46, 0x2A, 0x8D, 0x10, 0x04, 0x1A, 0x28, 0x2C, 0xF3, 0xC9, 0xDE,
+                0x08, 0x43, 0x56, 0x66, 0x95, 0x6D
+            ]]
+        );
+    }
 }
@@ -1,82 +1,121 @@
-use crate::{constants::REMAINDER_BITS, error::QRError, utils, ErrorCorrection, Mode};
-
-/// Encodes a segment of data
-pub(crate) fn encode_segment(version: usize, mode: &Mode, bytes: &[u8]) -> (Vec<bool>, Vec<bool>) {
-    let bit_count = get_bit_count_for_length(version, mode);
-    let mode_indicator = get_mode(mode, version);
-    let size = get_size(bytes, bit_count, mode);
-    let data = match mode {
-        Mode::ECI(_) => vec![],
-        _ => get_data(bytes, mode),
-    };
+use alloc::vec::Vec;
+
+use crate::{
+    bit_buffer::BitBuffer,
+    constants::{PAD_CODEWORDS, RMQR_COUNT_BITS},
+    error::QRError,
+    utils, ErrorCorrection, Mode,
+};
+
+/// Encodes a segment of data into its header, the mode indicator and character count, and its
+/// data bits
+pub(crate) fn encode_segment(
+    version: usize,
+    mode: &Mode,
+    bytes: &[u8],
+) -> Result<(BitBuffer, BitBuffer), QRError> {
+    let bit_count = get_bit_count_for_length(version, mode)?;
+
+    // Some rMQR versions hold more characters of a mode than the character count indicator can
+    // count, so longer data is encoded as consecutive segments of the mode
+    let max_bytes = match mode {
+        Mode::Kanji | Mode::Hanzi => 2,
+        _ => 1,
+    } * ((1 << bit_count) - 1);
+    if !matches!(mode, Mode::ECI(_) | Mode::FNC1First | Mode::FNC1Second(_))
+        && bytes.len() > max_bytes
+    {
+        let (header, mut data) = encode_segment(version, mode, &bytes[..max_bytes])?;
+        let (rest_header, rest_data) = encode_segment(version, mode, &bytes[max_bytes..])?;
+        data.append(&rest_header);
+        data.append(&rest_data);
+        return Ok((header, data));
+    }
+
+    let mut header = get_mode(mode, version)?;
+    append_size(&mut header, bytes, bit_count, mode);
 
-    let mut mode = vec![];
-    mode.extend_from_slice(&mode_indicator);
-    mode.extend_from_slice(&size);
+    let mut data = BitBuffer::with_capacity(utils::num_of_bits(mode, bytes.len()));
+    append_data(&mut data, bytes, mode);
 
-    (mode, data)
+    Ok((header, data))
 }
 
-/// Cobines all vectors into one
+/// Append the headers and data bits of the segments to the buffer
+pub(crate) fn encode_segments(
+    segments: &[(Mode, Vec<u8>)],
+    version: usize,
+    data: &mut BitBuffer,
+) -> Result<(), QRError> {
+    for (mode, bytes) in segments {
+        let (header, bits) = encode_segment(version, mode, bytes)?;
+        data.append(&header);
+        data.append(&bits);
+    }
+
+    Ok(())
+}
+
+/// Terminate and pad the data in place, so its bytes are the data codewords
+///
+/// The final data codeword of M1 and M3 is only 4 bits long and is held in the high bits of
+/// the last byte.
 pub(crate) fn build_combined_data(
-    data: Vec<bool>,
+    combined_data: &mut BitBuffer,
     version: usize,
     error_correction: &ErrorCorrection,
-) -> Result<Vec<bool>, QRError> {
-    let mut combined_data = vec![];
+) -> Result<(), QRError> {
+    utils::check_error_correction(version, error_correction)?;
 
-    let data_codewords = utils::get_available_data_size(version, error_correction) as usize;
+    let data_codewords = utils::get_available_data_size(version, error_correction)? as usize;
 
-    if data_codewords == 0 {
-        return Err(QRError::new("Invalid version"));
+    if combined_data.len() > data_codewords {
+        return Err(QRError::DataTooLarge {
+            required_bits: combined_data.len(),
+            available_bits: data_codewords,
+            version: utils::version_from_number(version),
+            required_version: None,
+        });
     }
 
-    if data.len() > data_codewords as usize {
-        return Err(QRError::new("Data too large"));
-    }
-
-    // Add data
-    combined_data.extend_from_slice(&data);
-
-    // Add padding if necessary
-    let mut terminator = 0;
     let terminator_size = match version {
         1..=40 => 4,
         41 => 3,
         42 => 5,
         43 => 7,
         44 => 9,
-        _ => panic!("Invalid version"),
+        45..=76 => 3,
+        _ => {
+            return Err(QRError::InvalidVersion {
+                version: utils::version_from_number(version),
+            })
+        }
     };
 
-    while combined_data.len() < data_codewords as usize && terminator < terminator_size {
-        combined_data.push(false);
-        terminator += 1;
-    }
+    // Add the terminator, which is cut short if the symbol is full
+    let terminator = terminator_size.min(data_codewords - combined_data.len());
+    combined_data.append_bits(0, terminator);
 
-    // Add remainder bits
-    if version <= 40 {
-        // Add remainder bits so that the length is a multiple of multiple
-        while combined_data.len() % 8 != 0 {
-            combined_data.push(false);
-        }
-        let remainding_bytes = (data_codewords as usize - combined_data.len()) / 8;
+    // Pad to a codeword boundary
+    let padding = (8 - combined_data.len() % 8) % 8;
+    combined_data.append_bits(0, padding.min(data_codewords - combined_data.len()));
 
-        for i in 0..remainding_bytes {
-            combined_data.extend_from_slice(&REMAINDER_BITS[i % 2]);
-        }
-    } else if version <= 44 {
-        while combined_data.len() < data_codewords as usize {
-            combined_data.push(false);
-        }
+    // Fill the full codewords with pad codewords
+    let mut i = 0;
+    while combined_data.len() + 8 <= data_codewords {
+        combined_data.append_byte(PAD_CODEWORDS[i % 2]);
+        i += 1;
     }
 
-    Ok(combined_data)
+    // The final data codeword of M1 and M3 is only 4 bits long and is padded with zeros
+    combined_data.append_bits(0, data_codewords - combined_data.len());
+
+    Ok(())
 }
 
 /// Get the number of bits for the mode length indicator
-fn get_bit_count_for_length(version: usize, mode: &Mode) -> u32 {
-    match mode {
+pub(crate) fn get_bit_count_for_length(version: usize, mode: &Mode) -> Result<u32, QRError> {
+    let bits = match mode {
         Mode::Numeric => match version {
             1..=9 => 10,
             10..=26 => 12,
@@ -85,7 +124,8 @@ fn get_bit_count_for_length(version: usize, mode: &Mode) -> u32 {
             42 => 4,
             43 => 5,
             44 => 6,
-            _ => panic!("Invalid version"),
+            45..=76 => RMQR_COUNT_BITS[version - 45][0],
+            _ => return Err(unsupported(mode, version)),
         },
         Mode::Alphanumeric => match version {
             1..=9 => 9,
@@ -94,7 +134,8 @@ fn get_bit_count_for_length(version: usize, mode: &Mode) -> u32 {
             42 => 3,
             43 => 4,
             44 => 5,
-            _ => panic!("Invalid version"),
+            45..=76 => RMQR_COUNT_BITS[version - 45][1],
+            _ => return Err(unsupported(mode, version)),
         },
         Mode::Byte => match version {
             1..=9 => 8,
@@ -102,7 +143,8 @@ fn get_bit_count_for_length(version: usize, mode: &Mode) -> u32 {
             27..=40 => 16,
             43 => 4,
             44 => 5,
-            _ => panic!("Invalid version"),
+            45..=76 => RMQR_COUNT_BITS[version - 45][2],
+            _ => return Err(unsupported(mode, version)),
         },
         Mode::Kanji => match version {
             1..=9 => 8,
@@ -110,7 +152,14 @@ fn get_bit_count_for_length(version: usize, mode: &Mode) -> u32 {
             27..=40 => 12,
             43 => 3,
             44 => 4,
-            _ => panic!("Invalid version"),
+            45..=76 => RMQR_COUNT_BITS[version - 45][3],
+            _ => return Err(unsupported(mode, version)),
+        },
+        Mode::Hanzi => match version {
+            1..=9 => 8,
+            10..=26 => 10,
+            27..=40 => 12,
+            _ => return Err(unsupported(mode, version)),
         },
         Mode::ECI(mode) => match mode {
             0..=127 => 8,
@@ -118,156 +167,158 @@ fn get_bit_count_for_length(version: usize, mode: &Mode) -> u32 {
             16384..=999999 => 24,
             _ => 0,
         },
-    }
+        Mode::FNC1First => 0,
+        Mode::FNC1Second(_) => 8,
+    };
+
+    Ok(bits)
 }
 
 /// Get the mode indicator
-fn get_mode(mode: &Mode, version: usize) -> Vec<bool> {
-    match version {
+pub(crate) fn get_mode(mode: &Mode, version: usize) -> Result<BitBuffer, QRError> {
+    let (value, count) = match version {
         1..=40 => match mode {
-            Mode::Numeric => vec![false, false, false, true],
-            Mode::Alphanumeric => vec![false, false, true, false],
-            Mode::Byte => vec![false, true, false, false],
-            Mode::Kanji => vec![true, false, false, false],
-            Mode::ECI(_) => vec![false, true, true, true],
+            Mode::Numeric => (0b0001, 4),
+            Mode::Alphanumeric => (0b0010, 4),
+            Mode::Byte => (0b0100, 4),
+            Mode::Kanji => (0b1000, 4),
+            // Mode indicator followed by the subset indicator for GB 2312
+            Mode::Hanzi => (0b1101_0001, 8),
+            Mode::ECI(_) => (0b0111, 4),
+            Mode::FNC1First => (0b0101, 4),
+            Mode::FNC1Second(_) => (0b1001, 4),
         },
-        41 => vec![],
+        41 => (0, 0),
         42 => match mode {
-            Mode::Numeric => vec![false],
-            Mode::Alphanumeric => vec![true],
-            _ => panic!("Invalid mode"),
+            Mode::Numeric => (0b0, 1),
+            Mode::Alphanumeric => (0b1, 1),
+            _ => return Err(unsupported(mode, version)),
         },
         43 => match mode {
-            Mode::Numeric => vec![false, false],
-            Mode::Alphanumeric => vec![false, true],
-            Mode::Byte => vec![true, false],
-            Mode::Kanji => vec![true, true],
-            _ => panic!("Invalid mode"),
+            Mode::Numeric => (0b00, 2),
+            Mode::Alphanumeric => (0b01, 2),
+            Mode::Byte => (0b10, 2),
+            Mode::Kanji => (0b11, 2),
+            _ => return Err(unsupported(mode, version)),
         },
         44 => match mode {
-            Mode::Numeric => vec![false, false, false],
-            Mode::Alphanumeric => vec![false, false, true],
-            Mode::Byte => vec![false, true, false],
-            Mode::Kanji => vec![false, true, true],
-            _ => panic!("Invalid mode"),
+            Mode::Numeric => (0b000, 3),
+            Mode::Alphanumeric => (0b001, 3),
+            Mode::Byte => (0b010, 3),
+            Mode::Kanji => (0b011, 3),
+            _ => return Err(unsupported(mode, version)),
         },
-        _ => panic!("Invalid version"),
-    }
+        45..=76 => match mode {
+            Mode::Numeric => (0b001, 3),
+            Mode::Alphanumeric => (0b010, 3),
+            Mode::Byte => (0b011, 3),
+            Mode::Kanji => (0b100, 3),
+            Mode::FNC1First => (0b101, 3),
+            Mode::FNC1Second(_) => (0b110, 3),
+            Mode::ECI(_) => (0b111, 3),
+            Mode::Hanzi => return Err(unsupported(mode, version)),
+        },
+        _ => return Err(unsupported(mode, version)),
+    };
+
+    let mut indicator = BitBuffer::new();
+    indicator.append_bits(value, count);
+    Ok(indicator)
 }
 
-/// Get the size bits for the mode
-fn get_size(bytes: &[u8], bit_count: u32, mode: &Mode) -> Vec<bool> {
-    match mode {
-        Mode::Kanji => {
-            let size = bytes.len() as u32 / 2;
-            let mut size_bits = vec![];
-            for i in 0..bit_count {
-                size_bits.push((size >> (bit_count - i - 1)) & 1 == 1);
-            }
-            size_bits
-        }
-        Mode::ECI(mode) => {
-            let mut size_bits = vec![];
-            for i in 0..bit_count {
-                size_bits.push((mode >> (bit_count - i - 1)) & 1 == 1);
-            }
-            size_bits
-        }
-        _ => {
-            let size = bytes.len() as u32;
-            let mut size_bits = vec![];
-            for i in 0..bit_count {
-                size_bits.push((size >> (bit_count - i - 1)) & 1 == 1);
-            }
-            size_bits
-        }
+/// Get the error for a mode or version that is not supported
+fn unsupported(mode: &Mode, version: usize) -> QRError {
+    match version {
+        1..=76 => QRError::ModeNotAllowed {
+            mode: mode.clone(),
+            version: utils::version_from_number(version),
+        },
+        _ => QRError::InvalidVersion {
+            version: utils::version_from_number(version),
+        },
     }
 }
 
-/// Get the data bits for the encoded data
-fn get_data(bytes: &[u8], mode: &Mode) -> Vec<bool> {
-    let mut data = vec![];
+/// Append the character count, or the value of an ECI or FNC1 second position mode
+fn append_size(buffer: &mut BitBuffer, bytes: &[u8], bit_count: u32, mode: &Mode) {
+    let value = match mode {
+        Mode::Kanji | Mode::Hanzi => bytes.len() as u32 / 2,
+        // The designator is prefixed with 0, 10 or 110 for one, two or three bytes
+        Mode::ECI(mode) => match bit_count {
+            16 => *mode as u32 | 0x8000,
+            24 => *mode as u32 | 0xC00000,
+            _ => *mode as u32,
+        },
+        Mode::FNC1Second(indicator) => *indicator as u32,
+        _ => bytes.len() as u32,
+    };
+
+    buffer.append_bits(value, bit_count as usize);
+}
 
+/// Append the data bits of the encoded data
+fn append_data(buffer: &mut BitBuffer, bytes: &[u8], mode: &Mode) {
     match mode {
         Mode::Numeric => {
-            // Split text into groups of 3
+            // Groups of 3 digits take 10 bits, a remaining group of 1 or 2 digits 4 or 7 bits
             for chunk in bytes.chunks(3) {
-                let mut value = 0;
-                for (i, c) in chunk.iter().enumerate() {
-                    value += (*c as u32 - '0' as u32) * 10u32.pow((chunk.len() - i - 1) as u32);
-                }
-                match chunk.len() {
-                    1 => {
-                        for i in (0..4).rev() {
-                            data.push((value >> i) & 1 == 1);
-                        }
-                    }
-                    2 => {
-                        for i in (0..7).rev() {
-                            data.push((value >> i) & 1 == 1);
-                        }
-                    }
-                    3 => {
-                        for i in (0..10).rev() {
-                            data.push((value >> i) & 1 == 1);
-                        }
-                    }
-                    _ => {}
-                }
+                let value = chunk
+                    .iter()
+                    .fold(0, |value, c| value * 10 + (c - b'0') as u32);
+                buffer.append_bits(value, [0, 4, 7, 10][chunk.len()]);
             }
         }
         Mode::Alphanumeric => {
-            let mut chars = bytes.iter().map(|&c| c as char);
-            while let Some(c1) = chars.next() {
-                if chars.clone().count() == 0 {
-                    let value = get_alphanumeric_index(c1);
-                    for i in (0..6).rev() {
-                        data.push((value >> i) & 1 == 1);
-                    }
-                } else {
-                    let value = get_alphanumeric_index(c1) * 45
-                        + get_alphanumeric_index(chars.next().unwrap());
-                    for i in (0..11).rev() {
-                        data.push((value >> i) & 1 == 1);
-                    }
+            // Pairs of characters take 11 bits, a remaining character 6 bits
+            for chunk in bytes.chunks(2) {
+                match chunk {
+                    [c1, c2] => buffer.append_bits(
+                        get_alphanumeric_index(*c1 as char) * 45
+                            + get_alphanumeric_index(*c2 as char),
+                        11,
+                    ),
+                    _ => buffer.append_bits(get_alphanumeric_index(chunk[0] as char), 6),
                 }
             }
         }
         Mode::Byte => {
-            for byte in bytes {
-                for i in (0..8).rev() {
-                    data.push((byte >> i) & 1 == 1);
-                }
+            for &byte in bytes {
+                buffer.append_byte(byte);
             }
         }
         Mode::Kanji => {
             // Process Shift-JIS bytes in pairs
-            for chunk in bytes.chunks(2) {
-                if chunk.len() == 2 {
-                    // Convert two bytes to 13-bit value
-                    let mut value = ((chunk[0] as u16) << 8) | chunk[1] as u16;
-
-                    // Apply Shift-JIS conversion
-                    if (0x8140..=0x9FFC).contains(&value) {
-                        value -= 0x8140;
-                    } else if (0xE040..=0xEBBF).contains(&value) {
-                        value -= 0xC140;
-                    }
-
-                    // Convert to 13-bit format
-                    value = ((value >> 8) * 0xC0) + (value & 0xFF);
-
-                    // Add 13 bits to data
-                    for i in (0..13).rev() {
-                        data.push((value >> i) & 1 == 1);
-                    }
+            for chunk in bytes.chunks_exact(2) {
+                let mut value = ((chunk[0] as u32) << 8) | chunk[1] as u32;
+
+                // Apply Shift-JIS conversion
+                if (0x8140..=0x9FFC).contains(&value) {
+                    value -= 0x8140;
+                } else if (0xE040..=0xEBBF).contains(&value) {
+                    value -= 0xC140;
                 }
+
+                // Convert to 13-bit format
+                buffer.append_bits(((value >> 8) * 0xC0) + (value & 0xFF), 13);
             }
         }
-        Mode::ECI(_) => {}
-    }
+        Mode::Hanzi => {
+            // Process GB 2312 bytes in pairs
+            for chunk in bytes.chunks_exact(2) {
+                let mut value = ((chunk[0] as u32) << 8) | chunk[1] as u32;
 
-    data
+                if (0xA1A1..=0xAAFE).contains(&value) {
+                    value -= 0xA1A1;
+                } else if (0xB0A1..=0xFAFE).contains(&value) {
+                    value -= 0xA6A1;
+                }
+
+                // Convert to 13-bit format
+                buffer.append_bits(((value >> 8) * 0x60) + (value & 0xFF), 13);
+            }
+        }
+        Mode::ECI(_) | Mode::FNC1First | Mode::FNC1Second(_) => {}
+    }
 }
 
 /// Get the index of an alphanumeric character
@@ -287,3 +338,206 @@ fn get_alphanumeric_index(c: char) -> u32 {
         _ => 0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{correction, interleave};
+
+    /// Encode the segments, pad them and apply error correction, and get the data codewords
+    /// and the interleaved bits with their number
+    fn encode(
+        version: usize,
+        error_correction: ErrorCorrection,
+        segments: &[(Mode, &[u8])],
+    ) -> (Vec<u8>, usize, Vec<u8>) {
+        let mut data = BitBuffer::new();
+        for (mode, bytes) in segments {
+            let (header, bits) = encode_segment(version, mode, bytes).unwrap();
+            data.append(&header);
+            data.append(&bits);
+        }
+
+        build_combined_data(&mut data, version, &error_correction).unwrap();
+        let (blocks, ec_blocks) = blocks(version, &error_correction, data.bytes());
+        let mut result = BitBuffer::new();
+        interleave::interleave(&blocks, &ec_blocks, version, &mut result);
+
+        (data.bytes().to_vec(), result.len(), result.bytes().to_vec())
+    }
+
+    /// Get the data and error correction blocks of the data codewords
+    fn blocks(
+        version: usize,
+        error_correction: &ErrorCorrection,
+        data: &[u8],
+    ) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let (mut blocks, mut ec_blocks) = (Vec::new(), Vec::new());
+        correction::correction(version, error_correction, data, &mut blocks, &mut ec_blocks);
+        (blocks, ec_blocks)
+    }
+
+    #[test]
+    fn annex_example_codewords() {
+        let (data, bits, result) = encode(1, ErrorCorrection::M, &[(Mode::Numeric, b"01234567")]);
+
+        let expected_data = [
+            0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11,
+            0xEC, 0x11,
+        ];
+        let expected_ec = [0xA5, 0x24, 0xD4, 0xC1, 0xED, 0x36, 0xC7, 0x87, 0x2C, 0x55];
+        assert_eq!(data, expected_data);
+        assert_eq!(bits, 208);
+        assert_eq!(result, [&expected_data[..], &expected_ec[..]].concat());
+    }
+
+    /// Pack the bit fields, each a value and its number of bits, most significant bit first,
+    /// with zero bits up to a byte boundary
+    fn pack(fields: &[(u32, usize)]) -> Vec<u8> {
+        let bits: Vec<bool> = fields
+            .iter()
+            .flat_map(|&(value, len)| (0..len).rev().map(move |i| (value >> i) & 1 == 1))
+            .collect();
+
+        bits.chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (i, &bit)| byte | ((bit as u8) << (7 - i)))
+            })
+            .collect()
+    }
+
+    /// Get the bit fields of the codewords
+    fn fields(codewords: &[u8]) -> impl Iterator<Item = (u32, usize)> + '_ {
+        codewords.iter().map(|&c| (c as u32, 8))
+    }
+
+    /// Take the codewords of the blocks in turn, the first of each block, then the second of
+    /// each block and so on, skipping blocks that have run out
+    fn interleaved(blocks: &[Vec<u8>]) -> Vec<u8> {
+        let longest = blocks.iter().map(|b| b.len()).max().unwrap_or(0);
+
+        (0..longest)
+            .flat_map(|i| blocks.iter().filter_map(move |b| b.get(i).copied()))
+            .collect()
+    }
+
+    #[test]
+    fn codewords_hold_the_fields_of_the_segments() {
+        let segments: [(Mode, &[u8]); 3] = [
+            (Mode::Alphanumeric, b"HELLO WORLD"),
+            (Mode::Byte, "héllo".as_bytes()),
+            (Mode::Numeric, b"0123456789"),
+        ];
+        let (data, bits, result) = encode(5, ErrorCorrection::Q, &segments);
+
+        let expected = pack(&[
+            // Alphanumeric: indicator, count, then HE, LL, "O ", WO and RL as 45 * a + b and D
+            (0b0010, 4),
+            (11, 9),
+            (17 * 45 + 14, 11),
+            (21 * 45 + 21, 11),
+            (24 * 45 + 36, 11),
+            (32 * 45 + 24, 11),
+            (27 * 45 + 21, 11),
+            (13, 6),
+            // Byte: indicator, count, then the UTF-8 bytes
+            (0b0100, 4),
+            (6, 8),
+            (b'h' as u32, 8),
+            (0xC3, 8),
+            (0xA9, 8),
+            (b'l' as u32, 8),
+            (b'l' as u32, 8),
+            (b'o' as u32, 8),
+            // Numeric: indicator, count, then 012, 345, 678 and 9
+            (0b0001, 4),
+            (10, 10),
+            (12, 10),
+            (345, 10),
+            (678, 10),
+            (9, 4),
+            // Terminator
+            (0, 4),
+        ]);
+        assert_eq!(expected.len(), 24);
+
+        // Version 5 at level Q holds 62 data codewords, filled with alternating pad codewords
+        assert_eq!(data.len(), 62);
+        assert_eq!(data[..24], expected[..]);
+        assert!(data[24..].chunks(2).all(|pad| pad == PAD_CODEWORDS));
+
+        // It has 2 blocks of 15 and 2 blocks of 16 data codewords, each with 18 EC codewords
+        let (blocks, ec_blocks) = blocks(5, &ErrorCorrection::Q, &data);
+        let lengths: Vec<usize> = blocks.iter().map(|b| b.len()).collect();
+        assert_eq!(lengths, [15, 15, 16, 16]);
+        assert!(ec_blocks.iter().all(|b| b.len() == 18));
+        assert_eq!(blocks.concat(), data);
+
+        // The codewords are interleaved and followed by 7 remainder bits
+        assert_eq!(bits, (62 + 4 * 18) * 8 + 7);
+        assert_eq!(
+            result,
+            [interleaved(&blocks), interleaved(&ec_blocks), [0].to_vec()].concat()
+        );
+    }
+
+    #[test]
+    fn final_data_codeword_of_m1_and_m3_has_4_bits() {
+        // M1: no mode indicator and a 3 bit count; the 20 data bits leave no room for the
+        // terminator
+        let (data, bits, result) = encode(
+            41,
+            ErrorCorrection::DetectionOnly,
+            &[(Mode::Numeric, b"12345")],
+        );
+        let segment = [(5, 3), (123, 10), (45, 7)];
+        assert_eq!(data, pack(&segment));
+        assert_eq!(data, [0xA3, 0xDA, 0xD0]);
+
+        let (_, ec_blocks) = blocks(41, &ErrorCorrection::DetectionOnly, &data);
+        assert_eq!(ec_blocks.concat().len(), 2);
+        assert_eq!(bits, 20 + 2 * 8);
+        let expected: Vec<(u32, usize)> =
+            segment.into_iter().chain(fields(&ec_blocks[0])).collect();
+        assert_eq!(result, pack(&expected));
+
+        // M3: a 2 bit mode indicator, a 5 bit numeric count and a 7 bit terminator; 10 data
+        // codewords of 8 bits and a final one of 4 bits at level L
+        let (data, bits, result) = encode(43, ErrorCorrection::L, &[(Mode::Numeric, b"0123456")]);
+        let expected = pack(&[(0b00, 2), (7, 5), (12, 10), (345, 10), (6, 4), (0, 7)]);
+        assert_eq!(expected.len(), 5);
+        assert_eq!(data.len(), 11);
+        assert_eq!(data[..5], expected[..]);
+        assert_eq!(data[5..10], [0xEC, 0x11, 0xEC, 0x11, 0xEC]);
+        assert_eq!(data[10], 0);
+
+        let (_, ec_blocks) = blocks(43, &ErrorCorrection::L, &data);
+        assert_eq!(ec_blocks.concat().len(), 6);
+        assert_eq!(bits, 10 * 8 + 4 + 6 * 8);
+        let expected: Vec<(u32, usize)> = fields(&data[..10])
+            .chain([(0, 4)])
+            .chain(fields(&ec_blocks[0]))
+            .collect();
+        assert_eq!(result, pack(&expected));
+    }
+
+    #[test]
+    fn rmqr_codewords_hold_the_fields_of_the_segments() {
+        // R7x43: a 3 bit mode indicator, a 4 bit numeric count and a 3 bit terminator, with
+        // 6 data and 7 EC codewords at level M
+        let (data, bits, result) = encode(45, ErrorCorrection::M, &[(Mode::Numeric, b"123456")]);
+        let expected = pack(&[(0b001, 3), (6, 4), (123, 10), (456, 10), (0, 3)]);
+        assert_eq!(expected.len(), 4);
+        assert_eq!(data.len(), 6);
+        assert_eq!(data[..4], expected[..]);
+        assert_eq!(data[4..], PAD_CODEWORDS);
+
+        let (_, ec_blocks) = blocks(45, &ErrorCorrection::M, &data);
+        assert_eq!(ec_blocks.concat().len(), 7);
+        assert_eq!(bits, (6 + 7) * 8);
+        assert_eq!(result, [data, ec_blocks.concat()].concat());
+    }
+}
@@ -0,0 +1,250 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    mask::{DefaultMaskEvaluator, MaskOptions},
+    mode_selector::{self, Input},
+    qrcode::Buffers,
+    utils, ErrorCorrection, QRCode, QRError, Version,
+};
+
+/// An encoder for many payloads with the same settings.
+///
+/// The encoder builds the function patterns of every version it can select once, when it is
+/// created, and builds every QR code on a copy of them. The generator polynomials of the error
+/// correction are computed at compile time, so they are shared without an encoder. The version
+/// of each payload is the smallest one from the minimum version that fits it, and its modes are
+/// selected automatically. A batch reuses the bit buffer, codewords and error correction blocks
+/// of one QR code for the next; with the `parallel` feature, `par_encode_batch` encodes the
+/// payloads on the Rayon thread pool.
+///
+/// # Examples
+///
+/// ```rust
+/// # use qrforge::{ErrorCorrection, QRError, QrEncoder, Version};
+/// # fn main() -> Result<(), QRError> {
+/// let encoder = QrEncoder::new(ErrorCorrection::M, Version::V(1), Version::V(10))?;
+///
+/// let qr = encoder.encode(b"https://example.com/tickets/0001")?;
+/// assert_eq!(qr.dimension(), 29);
+///
+/// let tickets = (0..100).map(|i| format!("https://example.com/tickets/{:04}", i));
+/// let qr_codes: Vec<_> = encoder.encode_batch(tickets).collect();
+/// assert_eq!(qr_codes.len(), 100);
+/// assert!(qr_codes.iter().all(|qr| qr.is_ok()));
+/// # Ok(())
+/// # }
+/// ```
+pub struct QrEncoder {
+    error_correction: ErrorCorrection,
+    min_version: Version,
+    max_version: Version,
+    templates: Vec<(usize, QRCode)>,
+}
+
+impl QrEncoder {
+    /// Creates an encoder for the error correction level that selects versions from
+    /// `min_version` up to `max_version`.
+    ///
+    /// Micro QR versions are only selected if one of the bounds is a micro QR version, in the
+    /// order M1 to M4, 1 to 40, as with `QRBuilder::min_version` and `QRBuilder::max_version`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRError, QrEncoder, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let encoder = QrEncoder::new(ErrorCorrection::L, Version::M(2), Version::V(2))?;
+    /// assert_eq!(encoder.encode(b"12345")?.dimension(), 13);
+    ///
+    /// assert!(QrEncoder::new(ErrorCorrection::L, Version::V(5), Version::V(2)).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if a bound is invalid or an rMQR version, if the minimum version
+    /// comes after the maximum version or if no version between them supports the error
+    /// correction level.
+    pub fn new(
+        error_correction: ErrorCorrection,
+        min_version: Version,
+        max_version: Version,
+    ) -> Result<QrEncoder, QRError> {
+        let templates =
+            mode_selector::version_window(&min_version, &max_version, &error_correction)?
+                .into_iter()
+                .map(|version| Ok((version, QRCode::template(version)?)))
+                .collect::<Result<Vec<_>, QRError>>()?;
+
+        Ok(QrEncoder {
+            error_correction,
+            min_version,
+            max_version,
+            templates,
+        })
+    }
+
+    /// Encodes the payload into a QR code.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the payload does not fit in the maximum version or if no version
+    /// supports the error correction level.
+    pub fn encode(&self, data: &[u8]) -> Result<QRCode, QRError> {
+        self.encode_with(data, &mut Buffers::new())
+    }
+
+    /// Encode the payload with the buffers of the previous payload
+    ///
+    /// The data is encoded once, while its version is selected, and the QR code is built from
+    /// that encoding.
+    fn encode_with(&self, data: &[u8], buffers: &mut Buffers) -> Result<QRCode, QRError> {
+        let inputs = [Input::Auto(data.to_vec())];
+
+        let version = mode_selector::select_version(
+            &inputs,
+            &self.error_correction,
+            false,
+            Some(&self.min_version),
+            Some(&self.max_version),
+            &mut buffers.data,
+        )?;
+        let error_correction = utils::selected_error_correction(&version, &self.error_correction);
+        let version = utils::version_number(&version)?;
+
+        let template = match self.templates.iter().find(|(v, _)| *v == version) {
+            Some((_, template)) => template.clone(),
+            None => QRCode::template(version)?,
+        };
+
+        let mask = MaskOptions {
+            candidates: None,
+            evaluator: &DefaultMaskEvaluator,
+        };
+
+        QRCode::build_encoded(template, version, error_correction, buffers, &mask)
+    }
+
+    /// Encodes every payload into a QR code, in the order of the payloads.
+    ///
+    /// Returns an iterator over the results, which encodes each payload when it is reached and
+    /// reuses the buffers of the previous one. A payload that fails to encode does not stop the
+    /// others.
+    pub fn encode_batch<'a, I>(
+        &'a self,
+        payloads: I,
+    ) -> impl Iterator<Item = Result<QRCode, QRError>> + 'a
+    where
+        I: IntoIterator,
+        I::IntoIter: 'a,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut buffers = Buffers::new();
+        payloads
+            .into_iter()
+            .map(move |payload| self.encode_with(payload.as_ref(), &mut buffers))
+    }
+
+    /// Encodes every payload into a QR code in parallel.
+    ///
+    /// Returns an indexed parallel iterator over the results, which collects them in the order
+    /// of the payloads. Each thread reuses its buffers from one payload to the next. A payload
+    /// that fails to encode does not stop the others.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRError, QrEncoder, Version};
+    /// use rayon::prelude::*;
+    /// # fn main() -> Result<(), QRError> {
+    /// let encoder = QrEncoder::new(ErrorCorrection::M, Version::V(1), Version::V(10))?;
+    ///
+    /// let tickets: Vec<String> = (0..100)
+    ///     .map(|i| format!("https://example.com/tickets/{:04}", i))
+    ///     .collect();
+    /// let qr_codes: Vec<_> = encoder.par_encode_batch(&tickets).collect();
+    /// assert_eq!(qr_codes.len(), 100);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn par_encode_batch<'a, I>(
+        &'a self,
+        payloads: I,
+    ) -> impl IndexedParallelIterator<Item = Result<QRCode, QRError>> + 'a
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]> + Send + 'a,
+    {
+        payloads
+            .into_iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map_init(Buffers::new, move |buffers, payload| {
+                self.encode_with(payload.as_ref(), buffers)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the modules of the QR code row by row
+    fn modules(qr: &QRCode) -> Vec<bool> {
+        (0..qr.height())
+            .flat_map(|y| (0..qr.width()).map(move |x| qr.get(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn batches_reuse_the_buffers_of_larger_symbols() {
+        let encoder = QrEncoder::new(ErrorCorrection::Q, Version::M(2), Version::V(10)).unwrap();
+
+        // Each payload is encoded after a larger or smaller one, or one that failed
+        let long = [b'7'; 300];
+        let payloads: [&[u8]; 6] = [
+            b"https://example.com/tickets/0001",
+            b"12345",
+            &[b'a'; 400],
+            b"HELLO WORLD",
+            &long,
+            b"https://example.com/tickets/0002",
+        ];
+
+        let batch: Vec<_> = encoder.encode_batch(payloads).collect();
+        assert_eq!(batch.len(), payloads.len());
+        for (payload, qr) in payloads.iter().zip(&batch) {
+            match (encoder.encode(payload), qr) {
+                (Ok(expected), Ok(qr)) => assert_eq!(modules(qr), modules(&expected)),
+                (Err(_), Err(_)) => {}
+                _ => panic!("batch and single encoding differ"),
+            }
+        }
+        assert!(matches!(batch[2], Err(QRError::DataTooLarge { .. })));
+
+        let single = QRCode::builder()
+            .add_segment(None, payloads[1])
+            .min_version(Version::M(2))
+            .error_correction(ErrorCorrection::Q)
+            .build()
+            .unwrap();
+        assert_eq!(modules(batch[1].as_ref().unwrap()), modules(&single));
+
+        #[cfg(feature = "parallel")]
+        {
+            let parallel: Vec<_> = encoder.par_encode_batch(payloads).collect();
+            for (qr, expected) in parallel.iter().zip(&batch) {
+                match (qr, expected) {
+                    (Ok(qr), Ok(expected)) => assert_eq!(modules(qr), modules(expected)),
+                    (Err(_), Err(_)) => {}
+                    _ => panic!("parallel and sequential batches differ"),
+                }
+            }
+        }
+    }
+}
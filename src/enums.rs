@@ -1,3 +1,7 @@
+use core::fmt;
+
+use crate::QRError;
+
 /// Represents the various encoding modes available for QR codes.
 pub enum Mode {
     /// Numeric mode: encodes digits 0-9.
@@ -8,27 +12,47 @@ pub enum Mode {
     Byte,
     /// Kanji mode: encodes characters using Shift JIS.
     Kanji,
+    /// Hanzi mode: encodes simplified Chinese characters using GB 2312 (GB/T 18284).
+    Hanzi,
     /// Extended Channel Interpretation mode.
     ECI(usize),
+    /// FNC1 in first position: the data is formatted according to the GS1 General
+    /// Specifications.
+    FNC1First,
+    /// FNC1 in second position: the data is formatted according to an industry application
+    /// identified by the application indicator, which is a number from 0 to 99, or the ASCII
+    /// value of a letter plus 100.
+    FNC1Second(u8),
 }
 
-impl Mode {
-    /// Creates a `Mode` from a numeric value.
+impl TryFrom<usize> for Mode {
+    type Error = QRError;
+
+    /// Creates a `Mode` from its numeric value, as returned by `to_value`.
     ///
-    /// # Panics
+    /// # Examples
     ///
-    /// Panics if the value does not represent a valid mode.
-    pub fn from(value: usize) -> Mode {
+    /// ```rust
+    /// # use qrforge::Mode;
+    /// assert!(matches!(Mode::try_from(2), Ok(Mode::Byte)));
+    /// assert!(Mode::try_from(8).is_err());
+    /// ```
+    fn try_from(value: usize) -> Result<Mode, QRError> {
         match value {
-            0 => Mode::Numeric,
-            1 => Mode::Alphanumeric,
-            2 => Mode::Byte,
-            3 => Mode::Kanji,
-            4 => Mode::ECI(0),
-            _ => panic!("Invalid mode"),
+            0 => Ok(Mode::Numeric),
+            1 => Ok(Mode::Alphanumeric),
+            2 => Ok(Mode::Byte),
+            3 => Ok(Mode::Kanji),
+            4 => Ok(Mode::ECI(0)),
+            5 => Ok(Mode::Hanzi),
+            6 => Ok(Mode::FNC1First),
+            7 => Ok(Mode::FNC1Second(0)),
+            _ => Err(QRError::InvalidMode { value }),
         }
     }
+}
 
+impl Mode {
     /// Returns a numeric representation of the mode.
     pub fn to_value(&self) -> usize {
         match self {
@@ -37,6 +61,9 @@ impl Mode {
             Mode::Byte => 2,
             Mode::Kanji => 3,
             Mode::ECI(_) => 4,
+            Mode::Hanzi => 5,
+            Mode::FNC1First => 6,
+            Mode::FNC1Second(_) => 7,
         }
     }
 }
@@ -48,7 +75,10 @@ impl Clone for Mode {
             Mode::Alphanumeric => Mode::Alphanumeric,
             Mode::Byte => Mode::Byte,
             Mode::Kanji => Mode::Kanji,
+            Mode::Hanzi => Mode::Hanzi,
             Mode::ECI(v) => Mode::ECI(*v),
+            Mode::FNC1First => Mode::FNC1First,
+            Mode::FNC1Second(v) => Mode::FNC1Second(*v),
         }
     }
 }
@@ -63,24 +93,35 @@ pub enum ErrorCorrection {
     Q,
     /// High error correction.
     H,
+    /// Error detection only, the single level of micro QR code M1.
+    DetectionOnly,
 }
 
-impl ErrorCorrection {
-    /// Creates an `ErrorCorrection` level from a numeric value.
+impl TryFrom<usize> for ErrorCorrection {
+    type Error = QRError;
+
+    /// Creates an `ErrorCorrection` level from its numeric value, as returned by `to_value`.
     ///
-    /// # Panics
+    /// # Examples
     ///
-    /// Panics if the value does not represent a valid error correction level.
-    pub fn from(value: usize) -> ErrorCorrection {
+    /// ```rust
+    /// # use qrforge::ErrorCorrection;
+    /// assert!(matches!(ErrorCorrection::try_from(3), Ok(ErrorCorrection::H)));
+    /// assert!(ErrorCorrection::try_from(5).is_err());
+    /// ```
+    fn try_from(value: usize) -> Result<ErrorCorrection, QRError> {
         match value {
-            0 => ErrorCorrection::L,
-            1 => ErrorCorrection::M,
-            2 => ErrorCorrection::Q,
-            3 => ErrorCorrection::H,
-            _ => panic!("Invalid error correction level"),
+            0 => Ok(ErrorCorrection::L),
+            1 => Ok(ErrorCorrection::M),
+            2 => Ok(ErrorCorrection::Q),
+            3 => Ok(ErrorCorrection::H),
+            4 => Ok(ErrorCorrection::DetectionOnly),
+            _ => Err(QRError::InvalidErrorCorrection { value }),
         }
     }
+}
 
+impl ErrorCorrection {
     /// Returns a numeric representation of the error correction level.
     pub fn to_value(&self) -> usize {
         match self {
@@ -88,18 +129,57 @@ impl ErrorCorrection {
             ErrorCorrection::M => 1,
             ErrorCorrection::Q => 2,
             ErrorCorrection::H => 3,
+            ErrorCorrection::DetectionOnly => 4,
+        }
+    }
+}
+
+impl Clone for ErrorCorrection {
+    fn clone(&self) -> ErrorCorrection {
+        match self {
+            ErrorCorrection::L => ErrorCorrection::L,
+            ErrorCorrection::M => ErrorCorrection::M,
+            ErrorCorrection::Q => ErrorCorrection::Q,
+            ErrorCorrection::H => ErrorCorrection::H,
+            ErrorCorrection::DetectionOnly => ErrorCorrection::DetectionOnly,
         }
     }
 }
 
 /// Represents the QR code version.
 ///
-/// `Version` distinguishes standard QR codes (V) from micro QR codes (M).
+/// `Version` distinguishes standard QR codes (V) from micro QR codes (M) and rectangular micro
+/// QR codes (R).
 pub enum Version {
-    /// Standard QR code version.
+    /// Standard QR code version, from 1 to 40.
     V(usize),
-    /// Micro QR code version.
+    /// Micro QR code version, from 1 to 4.
     M(usize),
+    /// rMQR code (ISO/IEC 23941) of the height and width in modules, from R7x43 to R17x139.
+    ///
+    /// The heights are 7, 9, 11, 13, 15 and 17 and the widths 43, 59, 77, 99 and 139, and
+    /// heights 11 and 13 also have width 27. R7x27 and R9x27 do not exist.
+    R(usize, usize),
+}
+
+impl Clone for Version {
+    fn clone(&self) -> Version {
+        match self {
+            Version::V(v) => Version::V(*v),
+            Version::M(v) => Version::M(*v),
+            Version::R(height, width) => Version::R(*height, *width),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Version::V(v) => write!(f, "version {}", v),
+            Version::M(v) => write!(f, "micro QR code M{}", v),
+            Version::R(height, width) => write!(f, "rMQR code R{}x{}", height, width),
+        }
+    }
 }
 
 #[cfg(any(feature = "image", feature = "svg"))]
@@ -110,3 +190,15 @@ pub(crate) enum ErrorEnum {
     Width,
     Height,
 }
+
+#[cfg(any(feature = "image", feature = "svg"))]
+impl ErrorEnum {
+    /// Returns the name of the parameter.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ErrorEnum::Border => "border",
+            ErrorEnum::Width => "width",
+            ErrorEnum::Height => "height",
+        }
+    }
+}
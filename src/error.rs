@@ -1,41 +1,449 @@
-use std::error::Error;
-use std::fmt;
+use alloc::string::String;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::{error::Error, io};
+
+use crate::{mode_selector, segment, structured_append, ErrorCorrection, Mode, Version};
 
 /// Error type for QR code generation
 ///
+/// The variants tell what went wrong and carry the details, so they can be matched instead of
+/// the message.
+///
 /// # Example
 ///
 /// ``` rust
+/// # use qrforge::{QRCode, QRError, Version};
+/// let error = QRCode::builder()
+///     .add_segment(None, &[b'a'; 100])
+///     .version(Version::V(2))
+///     .build();
 ///
-/// # use qrcode::QRError;
-///
-/// fn main() -> Result<(), QRError> {
-///    Err(QRError::new("An error occurred"))
+/// match error {
+///     Err(QRError::DataTooLarge {
+///         required_bits,
+///         available_bits,
+///         ..
+///     }) => println!("{} bits too many", required_bits - available_bits),
+///     Err(e) => println!("{}", e),
+///     Ok(_) => {}
 /// }
 /// ```
-pub struct QRError {
-    message: String,
+pub enum QRError {
+    /// The data does not fit in the version at the error correction level.
+    DataTooLarge {
+        /// Number of bits of the encoded data
+        required_bits: usize,
+        /// Number of data bits of the version at the error correction level
+        available_bits: usize,
+        /// The version, which is the largest allowed one when the version is selected
+        /// automatically
+        version: Version,
+        /// The smallest version the data fits in, when the version is selected automatically
+        /// and the data only fits above the maximum version. `required_bits` is then the size
+        /// of the data in that version.
+        required_version: Option<Version>,
+    },
+    /// The version does not exist.
+    InvalidVersion {
+        /// The version
+        version: Version,
+    },
+    /// The minimum version is above the maximum version, or a bound is an rMQR version, which
+    /// is not selected automatically.
+    InvalidVersionRange {
+        /// The minimum version
+        min: Version,
+        /// The maximum version
+        max: Version,
+    },
+    /// The version does not support the error correction level.
+    ErrorCorrectionNotAllowed {
+        /// The error correction level
+        error_correction: ErrorCorrection,
+        /// The version
+        version: Version,
+    },
+    /// The version does not support the mode.
+    ModeNotAllowed {
+        /// The mode
+        mode: Mode,
+        /// The version
+        version: Version,
+    },
+    /// The number is not that of a mode.
+    InvalidMode {
+        /// The number
+        value: usize,
+    },
+    /// The number is not that of an error correction level.
+    InvalidErrorCorrection {
+        /// The number
+        value: usize,
+    },
+    /// The ECI assignment number is above 999999.
+    InvalidEci {
+        /// The ECI assignment number
+        eci: usize,
+    },
+    /// The mode is an FNC1 mode, which is set with `fnc1_first` or `fnc1_second` instead of
+    /// with a segment.
+    InvalidSegmentMode {
+        /// The mode
+        mode: Mode,
+    },
+    /// The FNC1 application indicator is neither 0 to 99 nor a letter plus 100.
+    InvalidApplicationIndicator {
+        /// The application indicator
+        application_indicator: u8,
+    },
+    /// The number of modules is not the width times the height, or the matrix is empty.
+    InvalidMatrix {
+        /// Number of modules
+        modules: usize,
+        /// Width of the matrix
+        width: usize,
+        /// Height of the matrix
+        height: usize,
+    },
+    /// A character of the data cannot be encoded in the mode.
+    InvalidCharacter {
+        /// Index of the character in the data or text
+        index: usize,
+        /// The mode
+        mode: Mode,
+    },
+    /// The mask pattern does not exist for the version.
+    InvalidMask {
+        /// The mask pattern
+        mask: u8,
+        /// The version
+        version: Version,
+    },
+    /// No data was added.
+    NoData,
+    /// The list of mask patterns to choose from is empty.
+    NoMaskCandidates,
+    /// The version does not support structured append, which only standard QR codes do.
+    StructuredAppendNotSupported {
+        /// The version
+        version: Version,
+    },
+    /// The version is too small to hold a character of the data next to the structured append
+    /// header.
+    VersionTooSmall {
+        /// The version
+        version: Version,
+    },
+    /// The data needs more than the 16 symbols of structured append.
+    TooManySymbols {
+        /// Number of symbols the data needs
+        symbols: usize,
+        /// The version
+        version: Version,
+    },
+    /// A GS1 element string is invalid.
+    GS1(GS1Error),
+    /// A parameter of the image or SVG renderer is invalid.
+    RenderParameter {
+        /// Name of the parameter: border, width or height
+        parameter: &'static str,
+    },
+    /// Writing the file failed.
+    #[cfg(feature = "std")]
+    Io(io::Error),
 }
 
-impl QRError {
-    /// Create a new error
-    pub fn new(message: &str) -> QRError {
-        QRError {
-            message: message.to_string(),
+#[cfg(feature = "std")]
+impl Error for QRError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QRError::Io(e) => Some(e),
+            QRError::GS1(e) => Some(e),
+            _ => None,
         }
     }
 }
 
-impl Error for QRError {}
+#[cfg(feature = "std")]
+impl From<io::Error> for QRError {
+    fn from(error: io::Error) -> QRError {
+        QRError::Io(error)
+    }
+}
 
 impl fmt::Display for QRError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self {
+            QRError::DataTooLarge {
+                required_bits,
+                available_bits,
+                version,
+                required_version: None,
+            } => write!(
+                f,
+                "Data of {} bits does not fit in {}, which holds {} bits",
+                required_bits, version, available_bits
+            ),
+            QRError::DataTooLarge {
+                required_bits,
+                available_bits,
+                version,
+                required_version: Some(required_version),
+            } => write!(
+                f,
+                "Data of {} bits does not fit in {}, which holds {} bits, it requires {}",
+                required_bits, version, available_bits, required_version
+            ),
+            QRError::InvalidVersion {
+                version: Version::V(v),
+            } => write!(f, "Invalid version {}, valid versions are 1 to 40", v),
+            QRError::InvalidVersion {
+                version: Version::M(v),
+            } => write!(
+                f,
+                "Invalid micro QR code version M{}, valid versions are M1 to M4",
+                v
+            ),
+            QRError::InvalidVersion {
+                version: Version::R(height, width),
+            } => write!(
+                f,
+                "Invalid rMQR code version R{}x{}, valid versions are R7x43 to R17x139",
+                height, width
+            ),
+            QRError::InvalidVersionRange {
+                min: min @ Version::R(..),
+                max,
+            }
+            | QRError::InvalidVersionRange {
+                min,
+                max: max @ Version::R(..),
+            } => write!(
+                f,
+                "Versions from {} to {} cannot be selected, rMQR codes are only built when \
+                 their version is set",
+                min, max
+            ),
+            QRError::InvalidVersionRange { min, max } => {
+                write!(f, "Minimum {} is above maximum {}", min, max)
+            }
+            QRError::ErrorCorrectionNotAllowed {
+                error_correction,
+                version,
+            } => {
+                let level = match error_correction {
+                    ErrorCorrection::L => "Error correction level L",
+                    ErrorCorrection::M => "Error correction level M",
+                    ErrorCorrection::Q => "Error correction level Q",
+                    ErrorCorrection::H => "Error correction level H",
+                    ErrorCorrection::DetectionOnly => "Error detection only",
+                };
+                write!(f, "{} is not supported in {}", level, version)
+            }
+            QRError::ModeNotAllowed { mode, version } => write!(
+                f,
+                "{} mode is not supported in {}",
+                mode_selector::mode_name(mode),
+                version
+            ),
+            QRError::InvalidMode { value } => {
+                write!(f, "Invalid mode {}, valid modes are 0 to 7", value)
+            }
+            QRError::InvalidErrorCorrection { value } => write!(
+                f,
+                "Invalid error correction level {}, valid levels are 0 to 4",
+                value
+            ),
+            QRError::InvalidEci { eci } => write!(
+                f,
+                "Invalid ECI assignment number {}, valid numbers are 0 to {}",
+                eci,
+                segment::MAX_ECI
+            ),
+            QRError::InvalidSegmentMode { mode } => write!(
+                f,
+                "{} modes are set with fnc1_first and fnc1_second",
+                mode_selector::mode_name(mode)
+            ),
+            QRError::InvalidApplicationIndicator {
+                application_indicator,
+            } => write!(
+                f,
+                "Invalid FNC1 application indicator {}",
+                application_indicator
+            ),
+            QRError::InvalidMatrix {
+                modules,
+                width,
+                height,
+            } => write!(
+                f,
+                "{} modules do not form a {}x{} matrix",
+                modules, width, height
+            ),
+            QRError::InvalidCharacter { index, mode } => write!(
+                f,
+                "Character at index {} cannot be encoded in {} mode",
+                index,
+                mode_selector::mode_name(mode).to_lowercase()
+            ),
+            QRError::InvalidMask {
+                mask,
+                version: version @ Version::R(..),
+            } => write!(
+                f,
+                "Invalid mask {} for {}, rMQR codes have the single mask 0",
+                mask, version
+            ),
+            QRError::InvalidMask { mask, version } => {
+                let count = if let Version::M(_) = version { 4 } else { 8 };
+                write!(
+                    f,
+                    "Invalid mask {} for {}, valid masks are 0 to {}",
+                    mask,
+                    version,
+                    count - 1
+                )
+            }
+            QRError::NoData => write!(f, "No segments provided"),
+            QRError::NoMaskCandidates => write!(f, "No mask candidates provided"),
+            QRError::StructuredAppendNotSupported { version } => {
+                write!(f, "Structured append is not supported in {}", version)
+            }
+            QRError::VersionTooSmall { version } => {
+                write!(f, "{} is too small for structured append", version)
+            }
+            QRError::TooManySymbols { symbols, version } => write!(
+                f,
+                "Data requires {} symbols of {}, structured append supports at most {}",
+                symbols,
+                version,
+                structured_append::MAX_SYMBOLS
+            ),
+            QRError::GS1(e) => write!(f, "{}", e),
+            QRError::RenderParameter { parameter } => write!(f, "Invalid {}", parameter),
+            #[cfg(feature = "std")]
+            QRError::Io(e) => write!(f, "{}", e),
+        }
     }
 }
 
 impl fmt::Debug for QRError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", self)
+    }
+}
+
+/// Error type for invalid GS1 element strings
+///
+/// # Example
+///
+/// ``` rust
+/// # use qrforge::{GS1Builder, GS1Error, QRError};
+/// let error = GS1Builder::new().gtin("09501101530009").build();
+///
+/// assert!(matches!(
+///     error,
+///     Err(QRError::GS1(GS1Error::InvalidCheckDigit { ref ai })) if ai == "01"
+/// ));
+/// ```
+pub enum GS1Error {
+    /// The Application Identifier is not supported.
+    UnsupportedIdentifier {
+        /// The Application Identifier
+        ai: String,
+    },
+    /// The data is shorter or longer than the Application Identifier allows.
+    InvalidLength {
+        /// The Application Identifier
+        ai: String,
+        /// Smallest number of characters
+        min: usize,
+        /// Largest number of characters
+        max: usize,
+    },
+    /// A character of the data is not allowed by the Application Identifier.
+    InvalidCharacter {
+        /// The Application Identifier
+        ai: String,
+        /// Index of the character in the data
+        index: usize,
+    },
+    /// The check digit does not match the data.
+    InvalidCheckDigit {
+        /// The Application Identifier
+        ai: String,
+    },
+    /// The data is not a date of the form YYMMDD.
+    InvalidDate {
+        /// The Application Identifier
+        ai: String,
+    },
+    /// No element strings were added.
+    NoElementStrings,
+}
+
+impl Clone for GS1Error {
+    fn clone(&self) -> GS1Error {
+        match self {
+            GS1Error::UnsupportedIdentifier { ai } => {
+                GS1Error::UnsupportedIdentifier { ai: ai.clone() }
+            }
+            GS1Error::InvalidLength { ai, min, max } => GS1Error::InvalidLength {
+                ai: ai.clone(),
+                min: *min,
+                max: *max,
+            },
+            GS1Error::InvalidCharacter { ai, index } => GS1Error::InvalidCharacter {
+                ai: ai.clone(),
+                index: *index,
+            },
+            GS1Error::InvalidCheckDigit { ai } => GS1Error::InvalidCheckDigit { ai: ai.clone() },
+            GS1Error::InvalidDate { ai } => GS1Error::InvalidDate { ai: ai.clone() },
+            GS1Error::NoElementStrings => GS1Error::NoElementStrings,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for GS1Error {}
+
+impl From<GS1Error> for QRError {
+    fn from(error: GS1Error) -> QRError {
+        QRError::GS1(error)
+    }
+}
+
+impl fmt::Display for GS1Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GS1Error::UnsupportedIdentifier { ai } => {
+                write!(f, "Unsupported application identifier {}", ai)
+            }
+            GS1Error::InvalidLength { ai, min, max } if min == max => {
+                write!(f, "Data of AI {} must be {} characters long", ai, max)
+            }
+            GS1Error::InvalidLength { ai, min, max } => write!(
+                f,
+                "Data of AI {} must be {} to {} characters long",
+                ai, min, max
+            ),
+            GS1Error::InvalidCharacter { ai, index } => write!(
+                f,
+                "Invalid character at index {} in data of AI {}",
+                index, ai
+            ),
+            GS1Error::InvalidCheckDigit { ai } => write!(f, "Invalid check digit for AI {}", ai),
+            GS1Error::InvalidDate { ai } => write!(f, "Invalid date for AI {}", ai),
+            GS1Error::NoElementStrings => write!(f, "No element strings provided"),
+        }
+    }
+}
+
+impl fmt::Debug for GS1Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
     }
 }
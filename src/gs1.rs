@@ -0,0 +1,353 @@
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{qrcode_builder::QRBuilder, GS1Error, QRError};
+
+/// Group separator, which ends a variable length element string
+const GS: u8 = 0x1D;
+
+/// Leading digits of the Application Identifiers of which the element strings have a
+/// predefined length and are never followed by a separator
+const PREDEFINED_LENGTH: [&str; 22] = [
+    "00", "01", "02", "03", "04", "11", "12", "13", "14", "15", "16", "17", "18", "19", "20", "31",
+    "32", "33", "34", "35", "36", "41",
+];
+
+/// Characters allowed in the data of alphanumeric Application Identifiers (GS1 CSET 82)
+const CSET_82: &[u8] =
+    b"!\"%&'()*+,-./0123456789:;<=>?ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz";
+
+/// Format of the data of an Application Identifier
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    /// Digits
+    Numeric,
+    /// Digits ending in a mod-10 check digit
+    CheckDigit,
+    /// A date as YYMMDD, where a day of 00 stands for the end of the month
+    Date,
+    /// Characters of CSET 82
+    Alphanumeric,
+}
+
+/// An Application Identifier: its leading digits, its length, the format and the minimum and
+/// maximum length of its data
+///
+/// Identifiers longer than their leading digits stand for a family, such as 3103 (net weight
+/// in kilograms with three decimals) for 310.
+type Identifier = (&'static str, usize, Format, usize, usize);
+
+/// Application Identifiers supported by the builder
+const IDENTIFIERS: [Identifier; 43] = [
+    ("00", 2, Format::CheckDigit, 18, 18),
+    ("01", 2, Format::CheckDigit, 14, 14),
+    ("02", 2, Format::CheckDigit, 14, 14),
+    ("10", 2, Format::Alphanumeric, 1, 20),
+    ("11", 2, Format::Date, 6, 6),
+    ("12", 2, Format::Date, 6, 6),
+    ("13", 2, Format::Date, 6, 6),
+    ("15", 2, Format::Date, 6, 6),
+    ("16", 2, Format::Date, 6, 6),
+    ("17", 2, Format::Date, 6, 6),
+    ("20", 2, Format::Numeric, 2, 2),
+    ("21", 2, Format::Alphanumeric, 1, 20),
+    ("22", 2, Format::Alphanumeric, 1, 20),
+    ("235", 3, Format::Alphanumeric, 1, 28),
+    ("240", 3, Format::Alphanumeric, 1, 30),
+    ("241", 3, Format::Alphanumeric, 1, 30),
+    ("242", 3, Format::Numeric, 1, 6),
+    ("250", 3, Format::Alphanumeric, 1, 30),
+    ("251", 3, Format::Alphanumeric, 1, 30),
+    ("253", 3, Format::Alphanumeric, 14, 30),
+    ("254", 3, Format::Alphanumeric, 1, 20),
+    ("30", 2, Format::Numeric, 1, 8),
+    ("31", 4, Format::Numeric, 6, 6),
+    ("32", 4, Format::Numeric, 6, 6),
+    ("33", 4, Format::Numeric, 6, 6),
+    ("34", 4, Format::Numeric, 6, 6),
+    ("35", 4, Format::Numeric, 6, 6),
+    ("36", 4, Format::Numeric, 6, 6),
+    ("37", 2, Format::Numeric, 1, 8),
+    ("390", 4, Format::Numeric, 1, 15),
+    ("392", 4, Format::Numeric, 1, 15),
+    ("400", 3, Format::Alphanumeric, 1, 30),
+    ("401", 3, Format::Alphanumeric, 1, 30),
+    ("402", 3, Format::CheckDigit, 17, 17),
+    ("403", 3, Format::Alphanumeric, 1, 30),
+    ("41", 3, Format::CheckDigit, 13, 13),
+    ("420", 3, Format::Alphanumeric, 1, 20),
+    ("422", 3, Format::Numeric, 3, 3),
+    ("7003", 4, Format::Numeric, 10, 10),
+    ("8004", 4, Format::Alphanumeric, 1, 30),
+    ("8020", 4, Format::Alphanumeric, 1, 25),
+    ("90", 2, Format::Alphanumeric, 1, 30),
+    ("9", 2, Format::Alphanumeric, 1, 90),
+];
+
+/// A builder for GS1 element strings.
+///
+/// Each element string consists of an Application Identifier (AI) and its data. The data is
+/// validated against the length and character set of the AI, and a separator is inserted after
+/// every element string that does not have a predefined length, except the last. Placing the
+/// element strings with a fixed length first therefore saves separators.
+///
+/// The element strings are encoded in a QR code with FNC1 in first position, split into the
+/// segments that take the fewest bits.
+///
+/// # Examples
+///
+/// ```rust
+/// # use qrforge::{ErrorCorrection, GS1Builder, GS1Error, QRError};
+/// # fn main() -> Result<(), QRError> {
+/// let gs1 = GS1Builder::new()
+///     .gtin("0950110153000")
+///     .expiry("261231")
+///     .batch("AB-123")
+///     .serial("0042");
+///
+/// assert_eq!(gs1.element_string()?, b"01095011015300031726123110AB-123\x1d210042");
+///
+/// let qr = gs1.build()?.error_correction(ErrorCorrection::Q).build()?;
+///
+/// let error = GS1Builder::new().gtin("09501101530009").build();
+/// assert!(matches!(
+///     error,
+///     Err(QRError::GS1(GS1Error::InvalidCheckDigit { .. }))
+/// ));
+/// # Ok(())
+/// # }
+/// ```
+pub struct GS1Builder {
+    elements: Vec<(String, String)>,
+    error: Option<GS1Error>,
+}
+
+impl Default for GS1Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GS1Builder {
+    /// Creates a new empty GS1Builder.
+    pub fn new() -> GS1Builder {
+        GS1Builder {
+            elements: vec![],
+            error: None,
+        }
+    }
+
+    /// Adds an element string.
+    ///
+    /// If the data of an AI that ends in a check digit is one digit short, the check digit is
+    /// computed and appended; otherwise the check digit is verified. An unknown AI or invalid
+    /// data makes building fail.
+    pub fn add(mut self, ai: &str, data: &str) -> Self {
+        match Self::validate(ai, data) {
+            Ok(data) => self.elements.push((ai.to_string(), data)),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Adds a Serial Shipping Container Code (AI 00) of 17 digits, or 18 with the check digit.
+    pub fn sscc(self, sscc: &str) -> Self {
+        self.add("00", sscc)
+    }
+
+    /// Adds a Global Trade Item Number (AI 01) of 13 digits, or 14 with the check digit.
+    pub fn gtin(self, gtin: &str) -> Self {
+        self.add("01", gtin)
+    }
+
+    /// Adds a batch or lot number (AI 10).
+    pub fn batch(self, batch: &str) -> Self {
+        self.add("10", batch)
+    }
+
+    /// Adds a production date (AI 11) as YYMMDD.
+    pub fn production_date(self, date: &str) -> Self {
+        self.add("11", date)
+    }
+
+    /// Adds a best before date (AI 15) as YYMMDD.
+    pub fn best_before(self, date: &str) -> Self {
+        self.add("15", date)
+    }
+
+    /// Adds an expiration date (AI 17) as YYMMDD.
+    pub fn expiry(self, date: &str) -> Self {
+        self.add("17", date)
+    }
+
+    /// Adds a serial number (AI 21).
+    pub fn serial(self, serial: &str) -> Self {
+        self.add("21", serial)
+    }
+
+    /// Returns the element strings, separated by GS characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError::GS1` if an element string is invalid or none was added.
+    pub fn element_string(&self) -> Result<Vec<u8>, QRError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone().into());
+        }
+
+        if self.elements.is_empty() {
+            return Err(GS1Error::NoElementStrings.into());
+        }
+
+        let mut data = vec![];
+        for (i, (ai, value)) in self.elements.iter().enumerate() {
+            data.extend_from_slice(ai.as_bytes());
+            data.extend_from_slice(value.as_bytes());
+
+            if i + 1 < self.elements.len() && !PREDEFINED_LENGTH.contains(&&ai[..2]) {
+                data.push(GS);
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Returns a QRBuilder for a QR code with FNC1 in first position holding the element
+    /// strings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError::GS1` if an element string is invalid or none was added.
+    pub fn build(self) -> Result<QRBuilder, QRError> {
+        let data = self.element_string()?;
+        Ok(QRBuilder::new().fnc1_first().add_segment(None, &data))
+    }
+
+    /// Validate the data of an AI, returning it with the check digit if it was left out
+    fn validate(ai: &str, data: &str) -> Result<String, GS1Error> {
+        let (_, _, format, min, max) = IDENTIFIERS
+            .iter()
+            .find(|(prefix, length, ..)| {
+                ai.len() == *length
+                    && ai.starts_with(prefix)
+                    && ai.bytes().all(|c| c.is_ascii_digit())
+            })
+            .ok_or_else(|| GS1Error::UnsupportedIdentifier { ai: ai.to_string() })?;
+
+        let mut data = data.to_string();
+        if *format == Format::CheckDigit && data.len() + 1 == *max {
+            if let Some(digit) = check_digit(&data) {
+                data.push(char::from(b'0' + digit));
+            }
+        }
+
+        if data.len() < *min || data.len() > *max {
+            return Err(GS1Error::InvalidLength {
+                ai: ai.to_string(),
+                min: *min,
+                max: *max,
+            });
+        }
+
+        let invalid = match format {
+            Format::Alphanumeric => data.bytes().position(|c| !CSET_82.contains(&c)),
+            _ => data.bytes().position(|c| !c.is_ascii_digit()),
+        };
+        if let Some(index) = invalid {
+            return Err(GS1Error::InvalidCharacter {
+                ai: ai.to_string(),
+                index,
+            });
+        }
+
+        match format {
+            Format::CheckDigit => {
+                let (digits, last) = data.split_at(data.len() - 1);
+                if check_digit(digits) != Some(last.as_bytes()[0] - b'0') {
+                    return Err(GS1Error::InvalidCheckDigit { ai: ai.to_string() });
+                }
+            }
+            Format::Date => {
+                let month = &data[2..4];
+                let day = &data[4..6];
+                if !("01"..="12").contains(&month) || day > "31" {
+                    return Err(GS1Error::InvalidDate { ai: ai.to_string() });
+                }
+            }
+            _ => {}
+        }
+
+        Ok(data)
+    }
+}
+
+/// Compute the GS1 mod-10 check digit of the digits
+///
+/// Starting from the rightmost digit, the digits are weighted 3 and 1 alternately. The check
+/// digit brings the sum up to a multiple of ten.
+fn check_digit(digits: &str) -> Option<u8> {
+    let mut sum = 0;
+    for (i, c) in digits.bytes().rev().enumerate() {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        let weight = if i.is_multiple_of(2) { 3 } else { 1 };
+        sum += (c - b'0') as u32 * weight;
+    }
+
+    Some(((10 - sum % 10) % 10) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(builder: GS1Builder) -> GS1Error {
+        match builder.element_string() {
+            Err(QRError::GS1(e)) => e,
+            _ => panic!("expected a GS1 error"),
+        }
+    }
+
+    #[test]
+    fn check_digit_is_appended_or_verified() {
+        let gs1 = GS1Builder::new().gtin("0950110153000");
+        assert_eq!(gs1.element_string().unwrap(), b"0109501101530003");
+
+        let e = error(GS1Builder::new().gtin("09501101530009"));
+        assert!(matches!(e, GS1Error::InvalidCheckDigit { ai } if ai == "01"));
+    }
+
+    #[test]
+    fn invalid_element_strings_have_typed_errors() {
+        let e = error(GS1Builder::new().add("99X", "1"));
+        assert!(matches!(e, GS1Error::UnsupportedIdentifier { ai } if ai == "99X"));
+
+        let e = error(GS1Builder::new().sscc("1234"));
+        assert!(matches!(
+            e,
+            GS1Error::InvalidLength { ai, min: 18, max: 18 } if ai == "00"
+        ));
+
+        let e = error(GS1Builder::new().batch("AB#1"));
+        assert!(matches!(e, GS1Error::InvalidCharacter { ai, index: 2 } if ai == "10"));
+
+        let e = error(GS1Builder::new().expiry("261331"));
+        assert!(matches!(e, GS1Error::InvalidDate { ai } if ai == "17"));
+
+        let e = error(GS1Builder::new());
+        assert!(matches!(e, GS1Error::NoElementStrings));
+    }
+
+    #[test]
+    fn first_error_is_kept() {
+        let e = error(GS1Builder::new().expiry("261331").gtin("09501101530009"));
+        assert!(matches!(e, GS1Error::InvalidDate { .. }));
+        assert_eq!(e.to_string(), "Invalid date for AI 17");
+    }
+}
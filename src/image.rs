@@ -1,5 +1,6 @@
 #![cfg(feature = "image")]
-use image::{ImageBuffer, Rgba};
+use image::{ImageBuffer, ImageError, Rgba};
+use std::io;
 
 use crate::{color::Color, enums::ErrorEnum, error::QRError, qrcode::QRCode};
 
@@ -9,12 +10,12 @@ use crate::{color::Color, enums::ErrorEnum, error::QRError, qrcode::QRCode};
 ///
 /// Generate an image from a QRCode and save it:
 ///
-/// ```rust
-/// # use qr_module::{QRCode, Mode, ErrorCorrection};
+/// ```rust,no_run
+/// # use qrforge::{ErrorCorrection, Mode, QRCode, Version};
 /// let qr = QRCode::builder()
 ///     .add_segment(Some(Mode::Byte), b"Hello world")
 ///     .error_correction(ErrorCorrection::L)
-///     .version(1.into())
+///     .version(Version::V(1))
 ///     .build()
 ///     .unwrap();
 ///
@@ -39,11 +40,11 @@ pub struct ImageQRCode {
 impl ImageQRCode {
     /// Creates a new ImageQRCode with default parameters based on the QR code's dimensions.
     pub(crate) fn new(qr_code: QRCode) -> Self {
-        let dimension = qr_code.dimension();
+        let (width, height) = (qr_code.width(), qr_code.height());
         ImageQRCode {
             qr_code,
-            width: dimension,
-            height: dimension,
+            width,
+            height,
             border: 0,
             border_color: Color::new(255, 255, 255, 255),
             dark_color: Color::new(0, 0, 0, 255),
@@ -57,9 +58,7 @@ impl ImageQRCode {
     /// If the provided border is too large such that the drawable area is smaller than
     /// the QR code dimension, an error is recorded.
     pub fn set_border(&mut self, border: usize) -> &mut Self {
-        if self.width - 2 * border < self.qr_code.dimension()
-            || self.height - 2 * border < self.qr_code.dimension()
-        {
+        if !self.fits(border) {
             self.error.push(ErrorEnum::Border);
         } else {
             self.error.retain(|e| *e != ErrorEnum::Border);
@@ -68,11 +67,23 @@ impl ImageQRCode {
         self
     }
 
+    /// Checks if the QR code with the border on both sides fits in the width and height.
+    fn fits(&self, border: usize) -> bool {
+        let fits = |size: usize, modules: usize| {
+            border
+                .checked_mul(2)
+                .and_then(|border| border.checked_add(modules))
+                .is_some_and(|needed| needed <= size)
+        };
+
+        fits(self.width, self.qr_code.width()) && fits(self.height, self.qr_code.height())
+    }
+
     /// Sets the image width.
     ///
-    /// The width must be at least as large as the QR code dimension.
+    /// The width must be at least as large as the QR code width and fit in a `u32`.
     pub fn set_width(&mut self, width: usize) -> &mut Self {
-        if width < self.qr_code.dimension() {
+        if width < self.qr_code.width() || u32::try_from(width).is_err() {
             self.error.push(ErrorEnum::Width);
         } else {
             self.error.retain(|e| *e != ErrorEnum::Width);
@@ -83,9 +94,9 @@ impl ImageQRCode {
 
     /// Sets the image height.
     ///
-    /// The height must be at least as large as the QR code dimension.
+    /// The height must be at least as large as the QR code height and fit in a `u32`.
     pub fn set_height(&mut self, height: usize) -> &mut Self {
-        if height < self.qr_code.dimension() {
+        if height < self.qr_code.height() || u32::try_from(height).is_err() {
             self.error.push(ErrorEnum::Height);
         } else {
             self.error.retain(|e| *e != ErrorEnum::Height);
@@ -116,8 +127,17 @@ impl ImageQRCode {
     ///
     /// Returns an error if any of the parameters are invalid.
     pub fn build_image(&self) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, QRError> {
-        if !self.error.is_empty() {
-            return Err(QRError::new("Invalid parameters"));
+        if let Some(e) = self.error.first() {
+            return Err(QRError::RenderParameter {
+                parameter: e.name(),
+            });
+        }
+
+        // The width or height may have been reduced after the border was set
+        if !self.fits(self.border) {
+            return Err(QRError::RenderParameter {
+                parameter: ErrorEnum::Border.name(),
+            });
         }
 
         let border_color = Rgba([
@@ -143,12 +163,12 @@ impl ImageQRCode {
 
         let mut img = ImageBuffer::new(self.width as u32, self.height as u32);
 
-        let pixel_size_width = (self.width - 2 * self.border) / self.qr_code.dimension();
-        let pixel_size_height = (self.height - 2 * self.border) / self.qr_code.dimension();
+        let pixel_size_width = (self.width - 2 * self.border) / self.qr_code.width();
+        let pixel_size_height = (self.height - 2 * self.border) / self.qr_code.height();
         let pixel_size = std::cmp::min(pixel_size_width, pixel_size_height);
 
-        let border_width = (self.width - self.qr_code.dimension() * pixel_size) / 2;
-        let border_height = (self.height - self.qr_code.dimension() * pixel_size) / 2;
+        let border_width = (self.width - self.qr_code.width() * pixel_size) / 2;
+        let border_height = (self.height - self.qr_code.height() * pixel_size) / 2;
 
         // Draw background.
         for y in 0..self.height {
@@ -158,8 +178,8 @@ impl ImageQRCode {
         }
 
         // Draw QR code modules.
-        for y in 0..self.qr_code.dimension() {
-            for x in 0..self.qr_code.dimension() {
+        for y in 0..self.qr_code.height() {
+            for x in 0..self.qr_code.width() {
                 let color = if self.qr_code.get(x, y) {
                     dark_color
                 } else {
@@ -185,7 +205,10 @@ impl ImageQRCode {
     /// Returns an error if the image cannot be saved.
     pub fn build_image_file(&self, path: &str) -> Result<(), QRError> {
         let img = self.build_image()?;
-        img.save(path).map_err(|e| QRError::new(&e.to_string()))?;
+        img.save(path).map_err(|e| match e {
+            ImageError::IoError(e) => QRError::Io(e),
+            e => QRError::Io(io::Error::other(e)),
+        })?;
         Ok(())
     }
 }
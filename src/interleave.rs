@@ -1,49 +1,40 @@
-use crate::constants::REMAINING_BITS;
+use alloc::vec::Vec;
 
-/// Interleave the data and error correction blocks
+use crate::{bit_buffer::BitBuffer, constants::REMAINING_BITS};
+
+/// Interleave the data and error correction blocks into the bits placed in the symbol
+///
+/// The final data codeword of M1 and M3 is only 4 bits long, so only the high bits of its byte
+/// are placed. These versions have a single block, so it is the last data codeword.
 pub(crate) fn interleave(
-    blocks: Vec<Vec<Vec<bool>>>,
-    ec_blocks: Vec<Vec<Vec<bool>>>,
+    blocks: &[Vec<u8>],
+    ec_blocks: &[Vec<u8>],
     version: usize,
-) -> Vec<bool> {
-    let interleave_ec = interleave_blocks(&ec_blocks);
-    let interleave_data = interleave_blocks(&blocks);
+    result: &mut BitBuffer,
+) {
+    let data_codewords: usize = blocks.iter().map(|b| b.len()).sum();
 
-    let total_capacity = interleave_data.iter().map(|b| b.len()).sum::<usize>()
-        + interleave_ec.iter().map(|b| b.len()).sum::<usize>()
-        + REMAINING_BITS[version - 1] as usize;
+    result.clear();
 
-    let mut result: Vec<bool> = Vec::with_capacity(total_capacity);
-    for block in &interleave_data {
-        result.extend_from_slice(block);
+    let half_codeword = matches!(version, 41 | 43);
+    for (i, codeword) in interleave_blocks(blocks).enumerate() {
+        if half_codeword && i == data_codewords - 1 {
+            result.append_bits((codeword >> 4) as u32, 4);
+        } else {
+            result.append_byte(codeword);
+        }
     }
-    for block in &interleave_ec {
-        result.extend_from_slice(block);
+
+    for codeword in interleave_blocks(ec_blocks) {
+        result.append_byte(codeword);
     }
 
-    let remainder_bits = get_remainder_bits(version);
-    result.extend_from_slice(&remainder_bits);
-    result
+    result.append_bits(0, REMAINING_BITS[version - 1] as usize);
 }
 
-/// Interleave the blocks
-fn interleave_blocks(blocks: &Vec<Vec<Vec<bool>>>) -> Vec<Vec<bool>> {
-    let mut result: Vec<Vec<bool>> = Vec::new();
+/// Interleave the blocks, taking a codeword from each block in turn
+fn interleave_blocks(blocks: &[Vec<u8>]) -> impl Iterator<Item = u8> + '_ {
     let max_length = blocks.iter().map(|b| b.len()).max().unwrap_or(0);
-    result.reserve(max_length * blocks.len());
-
-    for i in 0..max_length {
-        for block in blocks {
-            if let Some(data) = block.get(i) {
-                result.push(data.clone());
-            }
-        }
-    }
-    result
-}
 
-/// Get the remainder bits
-fn get_remainder_bits(version: usize) -> Vec<bool> {
-    let remaining = REMAINING_BITS[version - 1];
-    vec![false; remaining as usize]
+    (0..max_length).flat_map(move |i| blocks.iter().filter_map(move |block| block.get(i).copied()))
 }
@@ -1,74 +1,123 @@
 //! # QR Code Generator
 //!
-//! This crate provides a QR code generator for encoding data into QR codes. The crate supports
-//! encoding data in numeric, alphanumeric, byte, and kanji modes. It also supports structured
-//! append for splitting data across multiple QR codes.
+//! This crate provides a QR code generator for encoding data into QR codes, micro QR codes and
+//! rMQR codes. The crate supports encoding data in numeric, alphanumeric, byte, kanji and hanzi
+//! modes. It also supports structured append for splitting data across multiple QR codes, and
+//! GS1 element strings through `GS1Builder`.
 //!
 //! The crate provides two main types: `QRCode` and `QRBuilder`. The `QRCode` type represents a
 //! QR code and provides methods for generating images in PNG and SVG formats. The `QRBuilder`
-//! type provides a builder pattern for creating QR codes with specific parameters.
+//! type provides a builder pattern for creating QR codes with specific parameters. Data can be
+//! added to it as validated `Segment`s, whose size can be computed up front.
 //!
 //! # Examples
 //!
 //! Creating a QR code:
 //!
 //! ```rust
+//! # use qrforge::{ErrorCorrection, Mode, QRCode, QRError, Version};
+//! # fn main() -> Result<(), QRError> {
 //! let qr = QRCode::builder()
 //!     .add_segment(Some(Mode::Byte), b"Hello world")
 //!     .error_correction(ErrorCorrection::L)
 //!     .version(Version::V(1))
 //!     .build()?;
+//! # Ok(())
+//! # }
 //! ```
 //!
-//! Generating a PNG image:
+//! Generating a PNG image (requires the `image` feature):
 //!
-//! ```rust
+//! ```rust,no_run
+//! # use qrforge::{QRCode, QRError};
+//! # fn main() -> Result<(), QRError> {
+//! # let qr = QRCode::builder().add_segment(None, b"Hello world").build()?;
+//! # #[cfg(feature = "image")]
 //! qr.image_builder()
 //!     .set_width(200)
 //!     .set_height(200)
 //!     .set_border(4)
 //!     .build_image_file("hello.png")?;
+//! # Ok(())
+//! # }
 //! ```
 //!
-//! Generating an SVG image:
+//! Generating an SVG image (requires the `svg` feature):
 //!
-//! ```rust
+//! ```rust,no_run
+//! # use qrforge::{QRCode, QRError};
+//! # fn main() -> Result<(), QRError> {
+//! # let qr = QRCode::builder().add_segment(None, b"Hello world").build()?;
+//! # #[cfg(all(feature = "svg", feature = "std"))]
 //! qr.svg_builder()
 //!     .set_width(200)
 //!     .set_height(200)
 //!     .set_border(4)
-//!     .build_image_file("hello.svg")?;
+//!     .build_svg_file("hello.svg")?;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! Creating a QR code with structured append:
 //!
 //! ```rust
+//! # use qrforge::{ErrorCorrection, Mode, QRCode, QRError, Version};
+//! # fn main() -> Result<(), QRError> {
 //! let qr = QRCode::builder()
 //!     .add_segment(Some(Mode::Byte), b"I read the newspaper")
 //!     .add_segment(Some(Mode::Numeric), b"1234567890")
 //!     .error_correction(ErrorCorrection::L)
 //!     .version(Version::V(1))
 //!     .build_with_structual_append()?;
+//! # Ok(())
+//! # }
 //! ```
 //!
+//! # `no_std`
+//!
+//! The `std` feature is enabled by default. Without it the crate builds under `#![no_std]`
+//! with `alloc`, and everything but writing files and printing to the terminal is available.
+//! The `image` and `parallel` features enable `std`.
+//!
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
+mod bit_buffer;
 mod constants;
 mod correction;
 mod encode;
+mod gb2312;
 mod interleave;
 mod matrix_builder;
 mod matrix_builder_micro;
+mod matrix_builder_rmqr;
 mod mode_selector;
+mod shift_jis;
+mod structured_append;
 mod utils;
 
+pub mod capacity;
+pub use capacity::{Capacity, Fit};
+
 pub mod color;
 pub use color::Color;
 
+pub mod encoder;
+pub use encoder::QrEncoder;
+
 pub mod enums;
 pub use enums::{ErrorCorrection, Mode, Version};
 
 pub mod error;
-pub use error::QRError;
+pub use error::{GS1Error, QRError};
+
+pub mod gs1;
+pub use gs1::GS1Builder;
+
+pub mod mask;
+pub use mask::{DefaultMaskEvaluator, MaskEvaluator};
 
 pub mod qrcode;
 pub use qrcode::QRCode;
@@ -76,6 +125,9 @@ pub use qrcode::QRCode;
 pub mod qrcode_builder;
 pub use qrcode_builder::QRBuilder;
 
+pub mod segment;
+pub use segment::Segment;
+
 pub mod image;
 #[cfg(feature = "image")]
 pub use image::ImageQRCode;
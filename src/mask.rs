@@ -0,0 +1,147 @@
+use alloc::vec::Vec;
+
+use crate::{matrix_builder, matrix_builder_micro, qrcode::QRCode, QRError};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Evaluates masked QR codes to choose the mask pattern.
+///
+/// Every candidate mask is applied to the QR code and evaluated with the format and version
+/// information of that mask in place, and the mask with the lowest penalty is used. Ties are
+/// broken in favor of the lowest mask number, so the choice is the same whether or not the
+/// masks are evaluated in parallel.
+///
+/// # Examples
+///
+/// An evaluator that prefers masks leaving the center light, to place a logo:
+///
+/// ```rust
+/// # use qrforge::{mask::{DefaultMaskEvaluator, MaskEvaluator}, QRCode, QRError};
+/// struct LightCenter;
+///
+/// impl MaskEvaluator for LightCenter {
+///     fn evaluate(&self, qr_code: &QRCode) -> i32 {
+///         let center = qr_code.dimension() / 2;
+///         let mut dark = 0;
+///         for y in center - 2..=center + 2 {
+///             for x in center - 2..=center + 2 {
+///                 dark += (qr_code.try_get(x, y) == Some(true)) as i32;
+///             }
+///         }
+///         DefaultMaskEvaluator.evaluate(qr_code) + dark * 100
+///     }
+/// }
+///
+/// # fn main() -> Result<(), QRError> {
+/// let qr = QRCode::builder()
+///     .add_segment(None, b"Hello world")
+///     .mask_evaluator(LightCenter)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait MaskEvaluator: Send + Sync {
+    /// Returns the penalty of the masked QR code, lower is better.
+    fn evaluate(&self, qr_code: &QRCode) -> i32;
+}
+
+/// The mask evaluation of ISO/IEC 18004.
+///
+/// Standard QR codes are scored with the penalty rules for adjacent modules, blocks, finder
+/// like patterns and the proportion of dark modules:
+///
+/// 1. A run of five or more modules of the same color in a row or column scores 3, plus 1 for
+///    every module past five.
+/// 2. Every 2x2 block of modules of the same color scores 3.
+/// 3. A dark, light, dark, light, dark pattern in the ratio 1:1:3:1:1 in a row or column,
+///    preceded or followed by at least four light modules, scores 40, and 80 if both. The
+///    area outside the symbol counts as light.
+/// 4. Every full 5% the proportion of dark modules deviates from 50% scores 10, that is
+///    10 * k for k = ⌊|dark% - 50| / 5⌋.
+///
+/// The rules are evaluated in a single pass over the rows. Micro QR codes are scored by the
+/// dark modules on their right and bottom edges; as that score is maximized, the penalty is
+/// how far it is below the highest possible score.
+///
+/// # Examples
+///
+/// ```rust
+/// # use qrforge::{mask::{DefaultMaskEvaluator, MaskEvaluator}, QRCode, QRError};
+/// # fn main() -> Result<(), QRError> {
+/// // 21 rows and columns with a run of 21 (19 each), 400 blocks, no finder like patterns and
+/// // only dark modules
+/// let dark = QRCode::from_modules(21, 21, &[true; 21 * 21])?;
+/// assert_eq!(DefaultMaskEvaluator.evaluate(&dark), 798 + 1200 + 0 + 100);
+///
+/// let checkerboard: Vec<bool> = (0..21 * 21).map(|i| (i % 21 + i / 21) % 2 == 0).collect();
+/// let checkerboard = QRCode::from_modules(21, 21, &checkerboard)?;
+/// assert_eq!(DefaultMaskEvaluator.evaluate(&checkerboard), 0);
+/// # Ok(())
+/// # }
+/// ```
+pub struct DefaultMaskEvaluator;
+
+impl MaskEvaluator for DefaultMaskEvaluator {
+    fn evaluate(&self, qr_code: &QRCode) -> i32 {
+        if qr_code.is_micro() {
+            let dimension = qr_code.dimension() as i32;
+            (dimension - 1) * 17 - matrix_builder_micro::calculate_evaluation(qr_code)
+        } else {
+            matrix_builder::calculate_penalty(qr_code)
+        }
+    }
+}
+
+/// Masks to consider and the evaluator to choose between them
+pub(crate) struct MaskOptions<'a> {
+    /// Candidate masks, or all masks of the version if `None`
+    pub(crate) candidates: Option<&'a [u8]>,
+    pub(crate) evaluator: &'a dyn MaskEvaluator,
+}
+
+/// Apply the candidate mask with the lowest penalty and its format information, returning the
+/// mask and its penalty
+///
+/// `apply_mask_pattern` inverts the data modules of the mask and `apply_information` writes
+/// the format and version information of a mask, so every candidate is evaluated on the
+/// complete symbol. A mask is undone by applying it again and the information of the next
+/// mask overwrites that of the previous one, so the candidates are evaluated on a single
+/// matrix, or on a copy per thread when they are evaluated in parallel.
+pub(crate) fn select_mask(
+    matrix: &mut QRCode,
+    candidates: &[u32],
+    evaluator: &dyn MaskEvaluator,
+    apply_mask_pattern: fn(&mut QRCode, u32),
+    apply_information: &(dyn Fn(&mut QRCode, u32) + Sync),
+) -> Result<(u32, i32), QRError> {
+    let evaluate = |matrix: &mut QRCode, &mask: &u32| {
+        apply_mask_pattern(matrix, mask);
+        apply_information(matrix, mask);
+        let penalty = evaluator.evaluate(matrix);
+        apply_mask_pattern(matrix, mask);
+        (mask, penalty)
+    };
+
+    #[cfg(not(feature = "parallel"))]
+    let results: Vec<(u32, i32)> = candidates
+        .iter()
+        .map(|mask| evaluate(matrix, mask))
+        .collect();
+
+    #[cfg(feature = "parallel")]
+    let results: Vec<(u32, i32)> = candidates
+        .par_iter()
+        .map_with(matrix.clone(), evaluate)
+        .collect();
+
+    let (mask, penalty) = results
+        .into_iter()
+        .min_by_key(|&(mask, penalty)| (penalty, mask))
+        .ok_or(QRError::NoMaskCandidates)?;
+
+    apply_mask_pattern(matrix, mask);
+    apply_information(matrix, mask);
+
+    Ok((mask, penalty))
+}
@@ -1,21 +1,18 @@
+use alloc::vec::Vec;
+
 use crate::{
+    bit_buffer::BitBuffer,
     constants::{
-        ALIGNMENT_PATTERN, ALIGNMENT_PATTERN_LOCATION, FINDER_PATTERN, FORMAT_INFORMATION, PATTERN,
+        ALIGNMENT_PATTERN, ALIGNMENT_PATTERN_LOCATION, FINDER_PATTERN, FORMAT_INFORMATION,
         VERSION_INFORMATION,
     },
+    mask::{self, MaskEvaluator},
     qrcode::{self, QRCode},
-    ErrorCorrection,
+    utils, ErrorCorrection, QRError,
 };
-#[cfg(feature = "parallel")]
-use rayon::prelude::*;
 
-/// Build the QR matrix
-pub(crate) fn build_qr_matrix(
-    matrix: &mut qrcode::QRCode,
-    version: usize,
-    error_correction: &ErrorCorrection,
-    data: Vec<bool>,
-) {
+/// Add the function patterns and reserve the areas of the format and version information
+pub(crate) fn add_function_patterns(matrix: &mut qrcode::QRCode, version: usize) {
     add_finder_patterns(matrix);
 
     add_separators(matrix);
@@ -27,11 +24,34 @@ pub(crate) fn build_qr_matrix(
     add_dark_module(matrix, version);
 
     add_reseverd_area(matrix, version);
+}
+
+/// Build the QR matrix on a matrix holding the function patterns
+pub(crate) fn build_qr_matrix(
+    matrix: &mut qrcode::QRCode,
+    version: usize,
+    error_correction: &ErrorCorrection,
+    data: &BitBuffer,
+    masks: &[u32],
+    evaluator: &dyn MaskEvaluator,
+) -> Result<(), QRError> {
+    add_data(matrix, data);
+
+    let ec_level = format_error_correction(error_correction, version)?;
+    let apply_information = |matrix: &mut QRCode, mask| {
+        apply_format_version_information(matrix, version, ec_level, mask)
+    };
 
-    let data_coordinates = add_data(matrix, data);
+    let (mask, penalty) = mask::select_mask(
+        matrix,
+        masks,
+        evaluator,
+        apply_mask_pattern,
+        &apply_information,
+    )?;
+    matrix.set_mask(mask, penalty);
 
-    let mask = apply_mask(matrix, data_coordinates);
-    apply_format_version_information(matrix, version, error_correction, mask);
+    Ok(())
 }
 
 /// Add the finder patterns
@@ -163,9 +183,8 @@ fn add_reserverd_area_v7_to_v40(matrix: &mut QRCode) {
 }
 
 /// Add the data to the matrix
-fn add_data(matrix: &mut QRCode, data: Vec<bool>) -> Vec<(i32, i32)> {
+fn add_data(matrix: &mut QRCode, data: &BitBuffer) {
     let dimension = matrix.dimension() as i32;
-    let mut visited = Vec::new();
 
     let mut current: (i32, i32) = (dimension - 1, dimension - 1);
     let mut direction = true; // false = up, true = down
@@ -177,17 +196,17 @@ fn add_data(matrix: &mut QRCode, data: Vec<bool>) -> Vec<(i32, i32)> {
         }
 
         if matrix.is_empty(current.0 as usize, current.1 as usize) {
-            matrix.set(current.0 as usize, current.1 as usize, data[data_index]);
+            matrix.set_data(current.0 as usize, current.1 as usize, data.get(data_index));
             data_index += 1;
-
-            visited.push((current.0, current.1));
         }
 
         if matrix.is_empty(current.0 as usize - 1, current.1 as usize) {
-            matrix.set(current.0 as usize - 1, current.1 as usize, data[data_index]);
+            matrix.set_data(
+                current.0 as usize - 1,
+                current.1 as usize,
+                data.get(data_index),
+            );
             data_index += 1;
-
-            visited.push((current.0 - 1, current.1));
         }
 
         if direction {
@@ -206,293 +225,217 @@ fn add_data(matrix: &mut QRCode, data: Vec<bool>) -> Vec<(i32, i32)> {
             direction = !direction;
         }
     }
-
-    visited
 }
 
-/// Apply the mask
-fn apply_mask(matrix: &mut QRCode, data_coordinates: Vec<(i32, i32)>) -> u32 {
-    {
-        #[cfg(not(feature = "parallel"))]
-        let results: Vec<(u32, i32, QRCode)> = (0..8)
-            .map(|i| {
-                let mut new_matrix = matrix.clone();
-                apply_mask_pattern(&mut new_matrix, i, &data_coordinates);
-                let penalty = calculate_penalty(&new_matrix);
-                (i, penalty, new_matrix)
-            })
-            .collect();
-
-        #[cfg(not(feature = "parallel"))]
-        let (mask, _, best_matrix) = results
-            .iter()
-            .min_by_key(|(_, penalty, _)| *penalty)
-            .unwrap();
-
-        #[cfg(feature = "parallel")]
-        let results: Vec<(u32, i32, QRCode)> = (0..8)
-            .into_par_iter()
-            .map(|i| {
-                let mut new_matrix = matrix.clone();
-                apply_mask_pattern(&mut new_matrix, i, &data_coordinates);
-                let penalty = calculate_penalty(&new_matrix);
-                (i, penalty, new_matrix)
-            })
-            .collect();
-
-        #[cfg(feature = "parallel")]
-        let (mask, _, best_matrix) = results
-            .par_iter()
-            .min_by_key(|(_, penalty, _)| *penalty)
-            .unwrap();
-
-        for i in 0..matrix.dimension() {
-            for j in 0..matrix.dimension() {
-                matrix.set(j, i, best_matrix.get(j, i));
-            }
-        }
-
-        *mask
-    }
+/// Apply the mask pattern to the data modules
+fn apply_mask_pattern(matrix: &mut QRCode, mask: u32) {
+    matrix.apply_mask(|i, j| mask_condition(mask, i, j));
 }
 
-/// Apply the mask pattern
-fn apply_mask_pattern(matrix: &mut QRCode, mask: u32, data_coordinates: &[(i32, i32)]) {
-    for (x, y) in data_coordinates.iter() {
-        let i = *y as usize;
-        let j = *x as usize;
-        match mask {
-            0 => {
-                if (i + j) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            1 => {
-                if i % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            2 => {
-                if j % 3 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            3 => {
-                if (i + j) % 3 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            4 => {
-                if (i / 2 + j / 3) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            5 => {
-                if (i * j) % 2 + (i * j) % 3 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            6 => {
-                if ((i * j) % 2 + (i * j) % 3) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            7 => {
-                if (((i + j) % 2) + ((i * j) % 3)) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            _ => {}
-        }
+/// Check if the mask pattern inverts the module in row i, column j
+fn mask_condition(mask: u32, i: usize, j: usize) -> bool {
+    match mask {
+        0 => (i + j).is_multiple_of(2),
+        1 => i.is_multiple_of(2),
+        2 => j.is_multiple_of(3),
+        3 => (i + j).is_multiple_of(3),
+        4 => (i / 2 + j / 3).is_multiple_of(2),
+        5 => (i * j) % 2 + (i * j) % 3 == 0,
+        6 => ((i * j) % 2 + (i * j) % 3).is_multiple_of(2),
+        7 => ((i + j) % 2 + (i * j) % 3).is_multiple_of(2),
+        _ => false,
     }
 }
 
-/// Calculate the penalty
-fn calculate_penalty(matrix: &QRCode) -> i32 {
-    let mut penalty = 0;
-
-    penalty += calculate_penalty_rule_1(matrix);
-    penalty += calculate_penalty_rule_2(matrix);
-    penalty += calculate_penalty_rule_3(matrix);
-    penalty += calculate_penalty_rule_4(matrix);
-
-    penalty
+/// Penalty of a run of five modules of the same color in a row or column
+const N1: i32 = 3;
+/// Penalty of a 2x2 block of modules of the same color
+const N2: i32 = 3;
+/// Penalty of a finder like pattern in a row or column
+const N3: i32 = 40;
+/// Penalty of every 5% the proportion of dark modules deviates from 50%
+const N4: i32 = 10;
+
+/// Calculate the penalty of the four rules of ISO/IEC 18004
+pub(crate) fn calculate_penalty(matrix: &QRCode) -> i32 {
+    rule_penalties(matrix).iter().sum()
 }
 
-/// Calculate the penalty for rule 1
-fn calculate_penalty_rule_1(matrix: &QRCode) -> i32 {
-    let mut penalty = 0;
-
-    let dimension = matrix.dimension();
+/// Calculate the penalty of each of the four rules in a single pass over the rows
+///
+/// The modules of each row are run length encoded for rules 1 and 3 while the runs of every
+/// column are followed alongside. The blocks of rule 2 are counted with the previous row and
+/// the dark modules of rule 4, a word at a time.
+fn rule_penalties(matrix: &QRCode) -> [i32; 4] {
+    let width = matrix.width();
+    let height = matrix.height();
+
+    let mut rows = Runs::new(width);
+    let mut columns: Vec<Runs> = (0..width).map(|_| Runs::new(height)).collect();
+    let mut boxes = 0;
+    let mut dark_count = 0;
 
-    // Horizontal
-    for i in 0..dimension {
-        let mut count = 1;
-        let mut current = matrix.get(i, 0);
-
-        for j in 1..dimension {
-            if matrix.get(i, j) == current {
-                count += 1;
-            } else {
-                if count >= 5 {
-                    penalty += count - 2;
-                }
+    for i in 0..height {
+        let row = matrix.row(i);
 
-                count = 1;
-                current = matrix.get(i, j);
-            }
+        for (j, column) in columns.iter_mut().enumerate() {
+            let dark = (row[j / 64] >> (j % 64)) & 1 == 1;
+            rows.push(dark);
+            column.push(dark);
         }
+        rows.finish();
 
-        if count >= 5 {
-            penalty += count - 2;
+        if i > 0 {
+            boxes += count_boxes(matrix.row(i - 1), row, width);
         }
-    }
 
-    // Vertical
-    for i in 0..dimension {
-        let mut count = 1;
-        let mut current = matrix.get(0, i);
-
-        for j in 1..dimension {
-            if matrix.get(j, i) == current {
-                count += 1;
-            } else {
-                if count >= 5 {
-                    penalty += count - 2;
-                }
-
-                count = 1;
-                current = matrix.get(j, i);
-            }
-        }
-
-        if count >= 5 {
-            penalty += count - 2;
-        }
+        dark_count += row.iter().map(|word| word.count_ones()).sum::<u32>() as i32;
     }
 
-    penalty
-}
+    columns.iter_mut().for_each(Runs::finish);
 
-/// Calculate the penalty for rule 2
-fn calculate_penalty_rule_2(matrix: &QRCode) -> i32 {
-    let boxes = count_boxes(matrix);
+    // k = |dark% - 50| / 5 rounded down, without rounding the percentage
+    let total = (width * height) as i32;
+    let k = (dark_count * 100 - total * 50).abs() / (total * 5);
 
-    boxes * 3
+    [
+        rows.adjacent + columns.iter().map(|runs| runs.adjacent).sum::<i32>(),
+        boxes * N2,
+        rows.finder_like + columns.iter().map(|runs| runs.finder_like).sum::<i32>(),
+        k * N4,
+    ]
 }
 
-/// Count the number of boxes
-fn count_boxes(matrix: &QRCode) -> i32 {
-    let dimension = matrix.dimension();
-    let mut count = 0;
+/// The runs of modules of a row or column, for penalty rules 1 and 3
+///
+/// The area outside the symbol counts as light, so a finder like pattern at the edge is
+/// followed by enough light modules. The penalties add up over the rows or columns the runs
+/// are used for.
+struct Runs {
+    /// Color of the current run
+    dark: bool,
+    /// Length of the current run
+    length: i32,
+    /// Lengths of the last seven finished runs, the most recent first
+    history: [i32; 7],
+    /// Number of modules of the row or column, the width of the light area around it
+    size: i32,
+    /// Penalty of rule 1
+    adjacent: i32,
+    /// Penalty of rule 3
+    finder_like: i32,
+}
 
-    for i in 0..dimension - 1 {
-        for j in 0..dimension - 1 {
-            if matrix.get(j, i) == matrix.get(j + 1, i)
-                && matrix.get(j, i) == matrix.get(j, i + 1)
-                && matrix.get(j, i) == matrix.get(j + 1, i + 1)
-            {
-                count += 1;
-            }
+impl Runs {
+    /// Start the runs of rows or columns of the size
+    fn new(size: usize) -> Runs {
+        Runs {
+            dark: false,
+            length: 0,
+            history: [0; 7],
+            size: size as i32,
+            adjacent: 0,
+            finder_like: 0,
         }
     }
 
-    count
-}
+    /// Add the next module
+    fn push(&mut self, dark: bool) {
+        if dark == self.dark {
+            self.length += 1;
+            self.adjacent += match self.length {
+                5 => N1,
+                6.. => 1,
+                _ => 0,
+            };
+            return;
+        }
 
-/// Calculate the penalty for rule 3
-fn calculate_penalty_rule_3(matrix: &QRCode) -> i32 {
-    count_occurences(matrix, &PATTERN) * 40
-}
+        self.finish_run();
 
-fn count_occurences(matrix: &QRCode, pattern: &[bool; 7]) -> i32 {
-    // maybe use bit manipulation
-    let mut count = 0;
-    let dimension = matrix.dimension();
+        // A finder like pattern ends with a light run
+        if dark {
+            self.finder_like += self.finder_patterns() * N3;
+        }
 
-    // Horizontal use && to check if it contains the pattern
-    for i in 0..dimension {
-        let mut current = 0;
-        let mut current_count = 0;
-
-        for j in 0..dimension {
-            if matrix.get(j, i) == pattern[current] {
-                current_count += 1;
-                current += 1;
-            } else {
-                current = 0;
-                current_count = 0;
-            }
+        self.dark = dark;
+        self.length = 1;
+    }
 
-            if current_count == 7 {
-                count += 1;
-                current = 0;
-                current_count = 0;
-            }
+    /// Finish the row or column with the light area after it, and start the next one with
+    /// the light area before it
+    fn finish(&mut self) {
+        if self.dark {
+            self.finish_run();
+            self.dark = false;
+            self.length = 0;
         }
-    }
 
-    // Vertical
-    for i in 0..dimension {
-        let mut current = 0;
-        let mut current_count = 0;
+        self.length += self.size;
+        self.finish_run();
 
-        for j in 0..dimension {
-            if matrix.get(i, j) == pattern[current] {
-                current_count += 1;
-                current += 1;
-            } else {
-                current = 0;
-                current_count = 0;
-            }
+        self.finder_like += self.finder_patterns() * N3;
 
-            if current_count == 7 {
-                count += 1;
-                current = 0;
-                current_count = 0;
-            }
+        self.length = 0;
+        self.history = [0; 7];
+    }
+
+    /// Add the current run to the history, where the first run includes the light area
+    /// before the row or column
+    fn finish_run(&mut self) {
+        if self.history[0] == 0 {
+            self.length += self.size;
         }
+
+        self.history.rotate_right(1);
+        self.history[0] = self.length;
     }
 
-    count
-}
+    /// Count the finder like patterns that end with the last light run: dark, light, dark,
+    /// light and dark runs in the ratio 1:1:3:1:1, preceded or followed by a light run at
+    /// least four times as long as a unit. A pattern with such a run on both sides counts
+    /// twice, once for each side.
+    fn finder_patterns(&self) -> i32 {
+        let [after, d1, l1, d3, l2, d2, before] = self.history;
+        let n = d1;
 
-/// Calculate the penalty for rule 4
-fn calculate_penalty_rule_4(matrix: &QRCode) -> i32 {
-    let mut dark_count = 0;
-    let dimension = matrix.dimension();
+        let core = n > 0 && l1 == n && d3 == 3 * n && l2 == n && d2 == n;
 
-    for i in 0..dimension {
-        for j in 0..dimension {
-            if matrix.get(j, i) {
-                dark_count += 1;
-            }
-        }
+        (core && after >= 4 * n && before >= n) as i32
+            + (core && before >= 4 * n && after >= n) as i32
     }
+}
 
-    let percentage = dark_count as f32 / (dimension * dimension) as f32 * 100.0;
+/// Count the 2x2 blocks of modules of the same color in two adjacent rows
+///
+/// The words of equal modules above each other and of modules equal to the one right of them
+/// are combined, 64 columns at a time.
+fn count_boxes(upper: &[u64], lower: &[u64], width: usize) -> i32 {
+    let mut count = 0;
 
-    let temp = percentage / 5.0;
-    let percentage_upper = temp.ceil() as i32;
-    let percentage_lower = temp.floor() as i32;
+    for w in 0..upper.len() {
+        // The modules one column to the right
+        let next = |row: &[u64]| (row[w] >> 1) | row.get(w + 1).map_or(0, |word| word << 63);
 
-    let penalty_upper = (percentage_upper - 10).abs();
-    let penalty_lower = (percentage_lower - 10).abs();
+        let vertical = !(upper[w] ^ lower[w]);
+        let vertical_next = !(next(upper) ^ next(lower));
+        let horizontal = !(upper[w] ^ next(upper));
+        let mut boxes = vertical & vertical_next & horizontal;
 
-    if penalty_upper < penalty_lower {
-        penalty_upper * 10
-    } else {
-        penalty_lower * 10
+        // A block starts in the columns before the last one
+        let columns = (width - 1).saturating_sub(w * 64);
+        if columns < 64 {
+            boxes &= (1 << columns) - 1;
+        }
+
+        count += boxes.count_ones() as i32;
     }
+
+    count
 }
 
 /// Apply the format and version information
-fn apply_format_version_information(
-    matrix: &mut QRCode,
-    version: usize,
-    error_correction: &ErrorCorrection,
-    mask: u32,
-) {
+fn apply_format_version_information(matrix: &mut QRCode, version: usize, ec_level: u32, mask: u32) {
     let dimension = matrix.dimension();
 
     if version >= 7 {
@@ -516,7 +459,7 @@ fn apply_format_version_information(
             }
         }
     }
-    let format_information_string = get_format_information(error_correction, mask);
+    let format_information_string = get_format_information(ec_level, mask);
 
     // top left
     let mut format_information_index = 0;
@@ -569,27 +512,36 @@ fn get_alignment_location(version: usize) -> Vec<(usize, usize)> {
         return alignment_pattern;
     }
 
-    for i in 0..ALIGNMENT_PATTERN_LOCATION[version - 2].len() {
-        for j in 0..ALIGNMENT_PATTERN_LOCATION[version - 2].len() {
-            alignment_pattern.push((
-                ALIGNMENT_PATTERN_LOCATION[version - 2][i],
-                ALIGNMENT_PATTERN_LOCATION[version - 2][j],
-            ));
+    let locations = ALIGNMENT_PATTERN_LOCATION[version - 2];
+
+    for &x in locations {
+        for &y in locations {
+            alignment_pattern.push((x, y));
         }
     }
 
     alignment_pattern
 }
 
-/// Get the format information
-fn get_format_information(error_correction: &ErrorCorrection, mask: u32) -> Vec<bool> {
-    let ec_level = match error_correction {
-        ErrorCorrection::L => 1,
-        ErrorCorrection::M => 0,
-        ErrorCorrection::Q => 3,
-        ErrorCorrection::H => 2,
-    };
+/// Get the error correction level indicator of the format information
+fn format_error_correction(
+    error_correction: &ErrorCorrection,
+    version: usize,
+) -> Result<u32, QRError> {
+    match error_correction {
+        ErrorCorrection::L => Ok(1),
+        ErrorCorrection::M => Ok(0),
+        ErrorCorrection::Q => Ok(3),
+        ErrorCorrection::H => Ok(2),
+        ErrorCorrection::DetectionOnly => Err(QRError::ErrorCorrectionNotAllowed {
+            error_correction: error_correction.clone(),
+            version: utils::version_from_number(version),
+        }),
+    }
+}
 
+/// Get the format information of the error correction level indicator and the mask
+fn get_format_information(ec_level: u32, mask: u32) -> Vec<bool> {
     let index = (ec_level << 3) | mask;
 
     let format_info = FORMAT_INFORMATION[index as usize];
@@ -615,3 +567,297 @@ fn get_version_information(version: usize) -> Vec<bool> {
 
     version_information
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Mode, Version};
+    use alloc::string::String;
+
+    /// Build the symbol of the worked example of ISO/IEC 18004 Annex I, "01234567" in version
+    /// 1 at level M, with the mask or with the automatically selected one
+    fn annex_example(mask: Option<u8>) -> QRCode {
+        let builder = QRCode::builder()
+            .add_segment(Some(Mode::Numeric), b"01234567")
+            .version(Version::V(1))
+            .error_correction(ErrorCorrection::M);
+
+        match mask {
+            Some(mask) => builder.mask(mask).build().unwrap(),
+            None => builder.build().unwrap(),
+        }
+    }
+
+    /// Create a QR code of the rows, where 1 is a dark module
+    fn from_rows(rows: &[&str]) -> QRCode {
+        let modules: Vec<bool> = rows
+            .iter()
+            .flat_map(|row| row.bytes())
+            .map(|b| b == b'1')
+            .collect();
+        QRCode::from_modules(rows[0].len(), rows.len(), &modules).unwrap()
+    }
+
+    /// Score the rules of ISO/IEC 18004 7.8.3 the simple way, to check the single pass scoring
+    ///
+    /// The lines are scanned as strings: runs for rule 1, every 2x2 block for rule 2 and the
+    /// finder like patterns of every unit size for rule 3, with the area outside the symbol as
+    /// wide as the line and light. Rule 4 is computed with floats.
+    fn reference_penalties(qr: &QRCode) -> [i32; 4] {
+        let (width, height) = (qr.width(), qr.height());
+        let rows = (0..height).map(|y| (0..width).map(|x| qr.get(x, y)).collect::<Vec<_>>());
+        let columns = (0..width).map(|x| (0..height).map(|y| qr.get(x, y)).collect::<Vec<_>>());
+        let lines: Vec<Vec<bool>> = rows.chain(columns).collect();
+
+        let mut adjacent = 0;
+        for line in &lines {
+            for run in line.chunk_by(|a, b| a == b) {
+                if run.len() >= 5 {
+                    adjacent += 3 + run.len() as i32 - 5;
+                }
+            }
+        }
+
+        let mut boxes = 0;
+        for y in 0..height - 1 {
+            for x in 0..width - 1 {
+                let color = qr.get(x, y);
+                if qr.get(x + 1, y) == color
+                    && qr.get(x, y + 1) == color
+                    && qr.get(x + 1, y + 1) == color
+                {
+                    boxes += 3;
+                }
+            }
+        }
+
+        let mut finder_like = 0;
+        for line in &lines {
+            let size = line.len();
+            let light = "0".repeat(size);
+            let line: String = line
+                .iter()
+                .map(|&dark| if dark { '1' } else { '0' })
+                .collect();
+            let line = [light.as_str(), &line, &light].concat();
+
+            for n in 1..=size / 7 {
+                let core = ["1", "0", "111", "0", "1"]
+                    .map(|run| run.repeat(n))
+                    .concat();
+                let (unit, wide) = ("0".repeat(n), "0".repeat(4 * n));
+                for pattern in [
+                    [unit.as_str(), &core, &wide].concat(),
+                    [wide.as_str(), &core, &unit].concat(),
+                ] {
+                    finder_like += 40 * line.match_indices(&pattern).count() as i32;
+                }
+            }
+        }
+
+        let dark = lines[..height]
+            .iter()
+            .flatten()
+            .filter(|&&dark| dark)
+            .count();
+        let percent = dark as f64 * 100.0 / (width * height) as f64;
+        let proportion = ((percent - 50.0).abs() / 5.0) as i32 * 10;
+
+        [adjacent, boxes, finder_like, proportion]
+    }
+
+    #[test]
+    fn annex_example_penalties_match_the_reference() {
+        let mut penalties = Vec::new();
+        for mask in 0..8 {
+            let qr = annex_example(Some(mask));
+            let expected = reference_penalties(&qr);
+            assert_eq!(rule_penalties(&qr), expected, "mask {}", mask);
+            assert_eq!(qr.penalty(), expected.iter().sum::<i32>(), "mask {}", mask);
+            penalties.push(qr.penalty());
+        }
+
+        // The first mask with the lowest penalty is selected
+        let lowest = penalties.iter().min().unwrap();
+        let mask = penalties.iter().position(|p| p == lowest).unwrap();
+        let qr = annex_example(None);
+        assert_eq!(qr.mask() as usize, mask);
+        assert_eq!(qr.penalty(), *lowest);
+    }
+
+    #[test]
+    fn penalties_of_wide_symbols_match_the_reference() {
+        let symbols: [(Version, &[u8]); 3] = [
+            (Version::V(7), b"https://example.com/tickets/0001"),
+            (Version::V(22), b"PALLET 0001 CARTON 0001-0048"),
+            (Version::V(40), b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ"),
+        ];
+
+        for (version, data) in symbols {
+            for mask in 0..8 {
+                let qr = QRCode::builder()
+                    .add_segment(None, data)
+                    .version(version.clone())
+                    .mask(mask)
+                    .build()
+                    .unwrap();
+                assert_eq!(
+                    rule_penalties(&qr),
+                    reference_penalties(&qr),
+                    "{} mask {}",
+                    version,
+                    mask
+                );
+            }
+        }
+
+        // Rectangular matrices across the 64 bit words of a row
+        let mut state: u32 = 1;
+        let modules: Vec<bool> = (0..130 * 70)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                state >> 30 != 0
+            })
+            .collect();
+        for (width, height) in [(130, 70), (70, 130), (65, 3)] {
+            let qr = QRCode::from_modules(width, height, &modules[..width * height]).unwrap();
+            assert_eq!(
+                rule_penalties(&qr),
+                reference_penalties(&qr),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn hand_built_patterns_are_scored_per_rule() {
+        // No runs of five, the four 2x2 blocks and no light modules
+        let dark = from_rows(&["111", "111", "111"]);
+        assert_eq!(rule_penalties(&dark), [0, 12, 0, 100]);
+
+        // A finder pattern: runs of 7 dark modules on the edges and of 5 light ones inside it,
+        // two 2x2 blocks on each side of the middle row of its center, three rows and three
+        // columns with a light area on both sides and 33 of 49 modules dark
+        let finder = from_rows(&[
+            "1111111", "1000001", "1011101", "1011101", "1011101", "1000001", "1111111",
+        ]);
+        assert_eq!(
+            rule_penalties(&finder),
+            [4 * (3 + 2) + 4 * 3, 4 * 3, 6 * 2 * 40, 3 * 10]
+        );
+    }
+
+    #[test]
+    fn annex_example_matches_the_reference_symbol() {
+        // The symbol of mask 0 as built by the qrcodegen library
+        let qr = annex_example(Some(0));
+
+        let symbol = from_rows(&[
+            "111111100011101111111",
+            "100000101110001000001",
+            "101110100110001011101",
+            "101110100101101011101",
+            "101110101101101011101",
+            "100000100001001000001",
+            "111111101010101111111",
+            "000000000000000000000",
+            "101010100010100010010",
+            "110100001011010100010",
+            "000110111011011101110",
+            "110011010101110110010",
+            "001001110111011100001",
+            "000000001010001000010",
+            "111111100000100010001",
+            "100000100010001001011",
+            "101110101110101011101",
+            "101110100101010101110",
+            "101110101101011100101",
+            "100000100001110111000",
+            "111111101001011100101",
+        ]);
+        for y in 0..21 {
+            for x in 0..21 {
+                assert_eq!(qr.get(x, y), symbol.get(x, y), "module {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn finder_like_pattern_counts_for_each_light_side() {
+        // Light on both sides, then only on the left within the symbol and beyond its edge
+        let both = from_rows(&["000010111010000"]);
+        let left = from_rows(&["000010111010100"]);
+
+        assert_eq!(rule_penalties(&both)[2], 80);
+        assert_eq!(rule_penalties(&left)[2], 40);
+    }
+
+    #[test]
+    fn dark_proportion_scores_every_full_five_percent() {
+        let penalty = |dark: usize| {
+            let modules: Vec<bool> = (0..100).map(|i| i < dark).collect();
+            rule_penalties(&QRCode::from_modules(10, 10, &modules).unwrap())[3]
+        };
+
+        assert_eq!(penalty(50), 0);
+        assert_eq!(penalty(46), 0);
+        assert_eq!(penalty(54), 0);
+        assert_eq!(penalty(45), 10);
+        assert_eq!(penalty(55), 10);
+        assert_eq!(penalty(39), 20);
+        assert_eq!(penalty(100), 100);
+    }
+
+    #[test]
+    fn version_information_matches_the_iso_table() {
+        // Table D.1 of ISO/IEC 18004
+        let expected = [
+            (7, "000111110010010100"),
+            (25, "011001000111100001"),
+            (33, "100001011011110000"),
+            (40, "101000110001101001"),
+        ];
+
+        for (version, bits) in expected {
+            let bits: Vec<bool> = bits.bytes().map(|b| b == b'1').collect();
+            assert_eq!(
+                get_version_information(version),
+                bits,
+                "version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn version_information_is_the_bch_code_of_the_version() {
+        for version in 7..=40 {
+            let mut remainder = version << 12;
+            for i in (12..18).rev() {
+                if remainder >> i & 1 == 1 {
+                    remainder ^= 0x1F25 << (i - 12);
+                }
+            }
+
+            let expected: Vec<bool> = (0..18)
+                .rev()
+                .map(|i| ((version << 12 | remainder) >> i) & 1 == 1)
+                .collect();
+            assert_eq!(
+                get_version_information(version),
+                expected,
+                "version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn dark_symbol_penalties_per_rule() {
+        let dark = QRCode::from_modules(21, 21, &[true; 21 * 21]).unwrap();
+
+        assert_eq!(rule_penalties(&dark), [798, 1200, 0, 100]);
+    }
+}
@@ -1,19 +1,15 @@
+use alloc::vec::Vec;
+
 use crate::{
+    bit_buffer::BitBuffer,
     constants::{FINDER_PATTERN, FORMAT_INFO_MICRO, MICRO_MAPPING},
+    mask::{self, MaskEvaluator},
     qrcode::{self, QRCode},
-    ErrorCorrection,
+    utils, ErrorCorrection, QRError,
 };
 
-#[cfg(feature = "parallel")]
-use rayon::prelude::*;
-
-/// Build the QR matrix
-pub(crate) fn build_qr_matrix(
-    matrix: &mut qrcode::QRCode,
-    version: usize,
-    error_correction: &ErrorCorrection,
-    data: Vec<bool>,
-) {
+/// Add the function patterns and reserve the area of the format information
+pub(crate) fn add_function_patterns(matrix: &mut qrcode::QRCode) {
     add_finder_patterns(matrix);
 
     add_separators(matrix);
@@ -21,11 +17,33 @@ pub(crate) fn build_qr_matrix(
     add_timing_patterns(matrix);
 
     add_reserverd_area(matrix);
+}
 
-    let data_coordinates = add_data(matrix, data);
-
-    let mask = apply_mask(matrix, data_coordinates);
-    apply_format_version_information(matrix, version, error_correction, mask);
+/// Build the QR matrix on a matrix holding the function patterns
+pub(crate) fn build_qr_matrix(
+    matrix: &mut qrcode::QRCode,
+    version: usize,
+    error_correction: &ErrorCorrection,
+    data: &BitBuffer,
+    masks: &[u32],
+    evaluator: &dyn MaskEvaluator,
+) -> Result<(), QRError> {
+    add_data(matrix, data);
+
+    let apply_information = |matrix: &mut QRCode, mask| {
+        apply_format_version_information(matrix, version, error_correction, mask)
+    };
+
+    let (mask, penalty) = mask::select_mask(
+        matrix,
+        masks,
+        evaluator,
+        apply_mask_pattern,
+        &apply_information,
+    )?;
+    matrix.set_mask(mask, penalty);
+
+    Ok(())
 }
 
 /// Add the finder patterns
@@ -73,26 +91,25 @@ fn add_reserverd_area(matrix: &mut qrcode::QRCode) {
 }
 
 /// Add the data to the matrix
-fn add_data(matrix: &mut QRCode, data: Vec<bool>) -> Vec<(i32, i32)> {
+fn add_data(matrix: &mut QRCode, data: &BitBuffer) {
     let dimension = matrix.dimension() as i32;
-    let mut visited = Vec::new();
 
     let mut current: (i32, i32) = (dimension - 1, dimension - 1);
     let mut direction = true; // false = up, true = down
     let mut data_index = 0;
     while data_index < data.len() && current.0 >= 0 {
         if matrix.is_empty(current.0 as usize, current.1 as usize) {
-            matrix.set(current.0 as usize, current.1 as usize, data[data_index]);
+            matrix.set_data(current.0 as usize, current.1 as usize, data.get(data_index));
             data_index += 1;
-
-            visited.push((current.0, current.1));
         }
 
         if matrix.is_empty(current.0 as usize - 1, current.1 as usize) {
-            matrix.set(current.0 as usize - 1, current.1 as usize, data[data_index]);
+            matrix.set_data(
+                current.0 as usize - 1,
+                current.1 as usize,
+                data.get(data_index),
+            );
             data_index += 1;
-
-            visited.push((current.0 - 1, current.1));
         }
 
         if direction {
@@ -111,83 +128,26 @@ fn add_data(matrix: &mut QRCode, data: Vec<bool>) -> Vec<(i32, i32)> {
             direction = !direction;
         }
     }
-
-    visited
 }
 
-/// Apply the mask
-fn apply_mask(matrix: &mut QRCode, data_coordinates: Vec<(i32, i32)>) -> u32 {
-    {
-        #[cfg(not(feature = "parallel"))]
-        let results: Vec<(u32, i32, QRCode)> = (0..4)
-            .map(|i| {
-                let mut new_matrix = matrix.clone();
-                apply_mask_pattern(&mut new_matrix, i, &data_coordinates);
-                let eval = calculate_evaluation(&new_matrix);
-                (i, eval, new_matrix)
-            })
-            .collect();
-
-        #[cfg(not(feature = "parallel"))]
-        let (mask, _, best_matrix) = results.iter().max_by_key(|(_, eval, _)| *eval).unwrap();
-
-        #[cfg(feature = "parallel")]
-        let results: Vec<(u32, i32, QRCode)> = (0..4)
-            .into_par_iter()
-            .map(|i| {
-                let mut new_matrix = matrix.clone();
-                apply_mask_pattern(&mut new_matrix, i, &data_coordinates);
-                let eval = calculate_evaluation(&new_matrix);
-                (i, eval, new_matrix)
-            })
-            .collect();
-
-        #[cfg(feature = "parallel")]
-        let (mask, _, best_matrix) = results.par_iter().max_by_key(|(_, eval, _)| *eval).unwrap();
-
-        for i in 0..matrix.dimension() {
-            for j in 0..matrix.dimension() {
-                matrix.set(j, i, best_matrix.get(j, i));
-            }
-        }
-
-        *mask
-    }
+/// Apply the mask pattern to the data modules
+fn apply_mask_pattern(matrix: &mut QRCode, mask: u32) {
+    matrix.apply_mask(|i, j| mask_condition(mask, i, j));
 }
 
-/// Apply the mask pattern
-fn apply_mask_pattern(matrix: &mut QRCode, mask: u32, data_coordinates: &[(i32, i32)]) {
-    for (x, y) in data_coordinates.iter() {
-        let i = *y as usize;
-        let j = *x as usize;
-        match mask {
-            0 => {
-                if i % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            1 => {
-                if (i / 2 + j / 3) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            2 => {
-                if ((i * j) % 2 + (i * j) % 3) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            3 => {
-                if (((i + j) % 2) + ((i * j) % 3)) % 2 == 0 {
-                    matrix.set(j, i, !matrix.get(j, i));
-                }
-            }
-            _ => {}
-        }
+/// Check if the mask pattern inverts the module in row i, column j
+fn mask_condition(mask: u32, i: usize, j: usize) -> bool {
+    match mask {
+        0 => i.is_multiple_of(2),
+        1 => (i / 2 + j / 3).is_multiple_of(2),
+        2 => ((i * j) % 2 + (i * j) % 3).is_multiple_of(2),
+        3 => ((i + j) % 2 + (i * j) % 3).is_multiple_of(2),
+        _ => false,
     }
 }
 
-/// Calculate the penalty
-fn calculate_evaluation(matrix: &QRCode) -> i32 {
+/// Calculate the evaluation score, which is highest for the best mask
+pub(crate) fn calculate_evaluation(matrix: &QRCode) -> i32 {
     let dimension = matrix.dimension();
 
     let mut sum1 = 0;
@@ -232,7 +192,7 @@ fn get_format_information(
     version: usize,
     mask: u32,
 ) -> Vec<bool> {
-    let ec_level = error_correction.to_value();
+    let ec_level = utils::ec_index(error_correction);
 
     let version = version - 41;
 
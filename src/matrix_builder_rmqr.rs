@@ -0,0 +1,324 @@
+use alloc::vec::Vec;
+
+use crate::{
+    bit_buffer::BitBuffer,
+    constants::{
+        ALIGNMENT_PATTERN, FINDER_PATTERN, RMQR_ALIGNMENT_PATTERN_LOCATION, RMQR_FORMAT_INFO_MASKS,
+    },
+    qrcode::{self, QRCode},
+    ErrorCorrection,
+};
+
+/// Generator polynomial of the BCH (18, 6) code of the format information
+const FORMAT_INFO_GENERATOR: u32 = 0x1F25;
+
+/// Add the function patterns and reserve the areas of the format information
+pub(crate) fn add_function_patterns(matrix: &mut qrcode::QRCode) {
+    add_finder_pattern(matrix);
+
+    add_separators(matrix);
+
+    add_finder_sub_pattern(matrix);
+
+    add_corner_finder_patterns(matrix);
+
+    add_alignment_patterns(matrix);
+
+    add_timing_patterns(matrix);
+
+    add_reserverd_area(matrix);
+}
+
+/// Build the rMQR matrix on a matrix holding the function patterns
+///
+/// rMQR codes have a single mask pattern, so no mask is evaluated.
+pub(crate) fn build_qr_matrix(
+    matrix: &mut qrcode::QRCode,
+    version: usize,
+    error_correction: &ErrorCorrection,
+    data: &BitBuffer,
+) {
+    add_data(matrix, data);
+
+    apply_mask_pattern(matrix);
+    matrix.set_mask(0, 0);
+
+    apply_format_information(matrix, version, error_correction);
+}
+
+/// Add the finder pattern in the top left corner
+fn add_finder_pattern(matrix: &mut qrcode::QRCode) {
+    FINDER_PATTERN.iter().enumerate().for_each(|(i, row)| {
+        row.iter().enumerate().for_each(|(j, &value)| {
+            matrix.set(j, i, value);
+        });
+    });
+}
+
+/// Add the separators, of which R7 only has the one right of the finder pattern
+fn add_separators(matrix: &mut qrcode::QRCode) {
+    let height = matrix.height();
+
+    for i in 0..8.min(height) {
+        matrix.set(7, i, false);
+    }
+
+    if height > 7 {
+        for i in 0..8 {
+            matrix.set(i, 7, false);
+        }
+    }
+}
+
+/// Add the finder sub pattern in the bottom right corner
+fn add_finder_sub_pattern(matrix: &mut qrcode::QRCode) {
+    let width = matrix.width();
+    let height = matrix.height();
+
+    ALIGNMENT_PATTERN.iter().enumerate().for_each(|(i, row)| {
+        row.iter().enumerate().for_each(|(j, &value)| {
+            matrix.set(width - 5 + j, height - 5 + i, value);
+        });
+    });
+}
+
+/// Add the corner finder patterns in the top right and bottom left corners
+fn add_corner_finder_patterns(matrix: &mut qrcode::QRCode) {
+    let width = matrix.width();
+    let height = matrix.height();
+
+    matrix.set(width - 1, 0, true);
+    matrix.set(width - 2, 0, true);
+    matrix.set(width - 1, 1, true);
+    matrix.set(width - 2, 1, false);
+
+    for i in 0..3 {
+        matrix.set(i, height - 1, true);
+    }
+
+    // The finder pattern and its separator take the corner of R7 and R9
+    if height >= 11 {
+        matrix.set(0, height - 2, true);
+        matrix.set(1, height - 2, false);
+    }
+}
+
+/// Add the alignment patterns on the top and bottom edges
+fn add_alignment_patterns(matrix: &mut qrcode::QRCode) {
+    let height = matrix.height();
+
+    for &center in alignment_pattern_location(matrix.width()) {
+        for i in 0..3 {
+            for j in 0..3 {
+                let value = i != 1 || j != 1;
+                matrix.set(center + j - 1, i, value);
+                matrix.set(center + j - 1, height - 3 + i, value);
+            }
+        }
+    }
+}
+
+/// Get the columns of the alignment patterns for the width
+fn alignment_pattern_location(width: usize) -> &'static [usize] {
+    RMQR_ALIGNMENT_PATTERN_LOCATION
+        .iter()
+        .find(|(w, _)| *w == width)
+        .map_or(&[], |(_, location)| location)
+}
+
+/// Add the timing patterns on the edges and through the alignment patterns
+fn add_timing_patterns(matrix: &mut qrcode::QRCode) {
+    let width = matrix.width();
+    let height = matrix.height();
+
+    for i in 0..width {
+        for j in [0, height - 1] {
+            if matrix.is_empty(i, j) {
+                matrix.set(i, j, i % 2 == 0);
+            }
+        }
+    }
+
+    let columns = [0, width - 1];
+    let columns = columns
+        .iter()
+        .chain(alignment_pattern_location(width).iter());
+
+    for &i in columns {
+        for j in 0..height {
+            if matrix.is_empty(i, j) {
+                matrix.set(i, j, j % 2 == 0);
+            }
+        }
+    }
+}
+
+/// Add the reserved area of the format information
+fn add_reserverd_area(matrix: &mut qrcode::QRCode) {
+    for (x, y) in format_information_coordinates(matrix.width(), matrix.height())
+        .into_iter()
+        .flatten()
+    {
+        matrix.set(x, y, false);
+    }
+}
+
+/// Get the coordinates of the bits of the two copies of the format information
+///
+/// The first copy is right of the finder pattern and the second left of and above the finder
+/// sub pattern, both from the least significant bit.
+fn format_information_coordinates(width: usize, height: usize) -> [Vec<(usize, usize)>; 2] {
+    let finder_side = (0..18).map(|i| (8 + i / 5, 1 + i % 5)).collect();
+
+    let sub_finder_side = (0..15)
+        .map(|i| (width - 8 + i / 5, height - 6 + i % 5))
+        .chain((0..3).map(|i| (width - 5 + i, height - 6)))
+        .collect();
+
+    [finder_side, sub_finder_side]
+}
+
+/// Add the data to the matrix
+///
+/// The data is placed in columns of two modules from right to left, alternately upwards and
+/// downwards. The rightmost column is a timing pattern, so the first pair of columns is left
+/// of it.
+fn add_data(matrix: &mut QRCode, data: &BitBuffer) {
+    let width = matrix.width() as i32;
+    let height = matrix.height() as i32;
+
+    let mut current: (i32, i32) = (width - 2, height - 1);
+    let mut upwards = true;
+    let mut data_index = 0;
+    while data_index < data.len() && current.0 >= 1 {
+        for x in [current.0, current.0 - 1] {
+            if data_index < data.len() && matrix.is_empty(x as usize, current.1 as usize) {
+                matrix.set_data(x as usize, current.1 as usize, data.get(data_index));
+                data_index += 1;
+            }
+        }
+
+        if upwards {
+            current.1 -= 1;
+        } else {
+            current.1 += 1;
+        }
+
+        if current.1 == height {
+            current.1 -= 1;
+            current.0 -= 2;
+            upwards = !upwards;
+        } else if current.1 == -1 {
+            current.1 += 1;
+            current.0 -= 2;
+            upwards = !upwards;
+        }
+    }
+}
+
+/// Apply the mask pattern, which inverts the data module in row i, column j where
+/// (i / 2 + j / 3) is even
+fn apply_mask_pattern(matrix: &mut QRCode) {
+    matrix.apply_mask(|i, j| (i / 2 + j / 3).is_multiple_of(2));
+}
+
+/// Apply the format information
+fn apply_format_information(
+    matrix: &mut QRCode,
+    version: usize,
+    error_correction: &ErrorCorrection,
+) {
+    let format_information = get_format_information(error_correction, version);
+    let coordinates = format_information_coordinates(matrix.width(), matrix.height());
+
+    for (coordinates, mask) in coordinates.iter().zip(RMQR_FORMAT_INFO_MASKS) {
+        let masked = format_information ^ mask;
+        for (i, &(x, y)) in coordinates.iter().enumerate() {
+            matrix.set(x, y, (masked >> i) & 1 == 1);
+        }
+    }
+}
+
+/// Get the format information
+///
+/// The error correction level bit, 0 for M and 1 for H, and the five bit version indicator
+/// are followed by the twelve bits of the BCH code.
+fn get_format_information(error_correction: &ErrorCorrection, version: usize) -> u32 {
+    let level = match error_correction {
+        ErrorCorrection::H => 1,
+        _ => 0,
+    };
+    let data = (level << 5 | (version - 45)) as u32;
+
+    let mut remainder = data << 12;
+    for i in (12..18).rev() {
+        if remainder & (1 << i) != 0 {
+            remainder ^= FORMAT_INFO_GENERATOR << (i - 12);
+        }
+    }
+
+    data << 12 | remainder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::VERSION_INFORMATION, Mode, Version};
+
+    #[test]
+    fn format_information_is_the_bch_code_of_the_level_and_version() {
+        assert_eq!(get_format_information(&ErrorCorrection::M, 45), 0);
+        assert_eq!(get_format_information(&ErrorCorrection::M, 46), 0x01F25);
+        assert_eq!(get_format_information(&ErrorCorrection::H, 76), 0x3FB85);
+
+        // The code is the one of the version information of ISO/IEC 18004
+        for data in 7..=40 {
+            let (error_correction, version) = match data {
+                0..=31 => (ErrorCorrection::M, data + 45),
+                _ => (ErrorCorrection::H, data - 32 + 45),
+            };
+            assert_eq!(
+                get_format_information(&error_correction, version),
+                VERSION_INFORMATION[data - 7],
+                "format information {}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn format_information_is_placed_beside_both_finder_patterns() {
+        let qr = QRCode::builder()
+            .add_segment(Some(Mode::Numeric), b"123456")
+            .version(Version::R(7, 43))
+            .error_correction(ErrorCorrection::M)
+            .build()
+            .unwrap();
+
+        // The format information of R7x43 at level M is zero, so the copies are the masks. The
+        // first is in columns 8 to 11 and the second in columns 35 to 40, from row 1 to row 5.
+        let finder_side = ["0101", "1011", "0110", "001.", "111."];
+        let sub_finder_side = ["110001", "111...", "000...", "100...", "110..."];
+
+        for (y, row) in finder_side.iter().enumerate() {
+            for (x, module) in row.bytes().enumerate() {
+                if module != b'.' {
+                    assert_eq!(qr.get(8 + x, 1 + y), module == b'1', "{}, {}", 8 + x, 1 + y);
+                }
+            }
+        }
+        for (y, row) in sub_finder_side.iter().enumerate() {
+            for (x, module) in row.bytes().enumerate() {
+                if module != b'.' {
+                    assert_eq!(
+                        qr.get(35 + x, 1 + y),
+                        module == b'1',
+                        "{}, {}",
+                        35 + x,
+                        1 + y
+                    );
+                }
+            }
+        }
+    }
+}
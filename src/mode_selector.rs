@@ -1,34 +1,903 @@
-use crate::{constants::ALPHANUMERIC, encode, utils, ErrorCorrection, Mode, QRError, Version};
-
-/// Select the mode for the data
-pub(crate) fn select_mode(data: &[u8]) -> Mode {
-    if data.iter().all(|&c| c.is_ascii_digit()) {
-        Mode::Numeric
-    } else if data.iter().all(|&c| ALPHANUMERIC.contains(&(c as char))) {
-        Mode::Alphanumeric
-    } else {
-        Mode::Byte
+use alloc::{string::String, vec, vec::Vec};
+
+use crate::{
+    bit_buffer::BitBuffer, constants::ALPHANUMERIC, encode, gb2312, segment, shift_jis, utils,
+    ErrorCorrection, Mode, QRError, Version,
+};
+
+/// ECI assignment number of ISO-8859-1
+const ISO_8859_1_ECI: usize = 3;
+
+/// ECI assignment number of Shift JIS
+pub(crate) const SHIFT_JIS_ECI: usize = 20;
+
+/// ECI assignment number of UTF-8
+pub(crate) const UTF_8_ECI: usize = 26;
+
+/// Group separator, which marks an FNC1 separator in the data of FNC1 symbols
+const GS: u8 = 0x1D;
+
+/// Modes considered when segmenting, indexed by `Mode::to_value`
+const MODES: [Mode; 4] = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte, Mode::Kanji];
+
+/// A character of the data to segment
+struct Character<'a> {
+    /// Bytes of the character in byte mode, if it may be encoded in byte mode
+    bytes: Option<&'a [u8]>,
+    /// Shift JIS code of the character, if it can be encoded in kanji mode
+    kanji: Option<u16>,
+}
+
+/// A segment as added to the builder, before its modes are resolved for a version
+pub(crate) enum Input {
+    /// Data encoded with the given mode
+    Fixed(Mode, Vec<u8>),
+    /// Data for which the modes are selected automatically
+    Auto(Vec<u8>),
+    /// Text for which the modes and character set are selected automatically
+    Text(String),
+    /// Text encoded in kanji mode once converted to Shift JIS
+    Kanji(String),
+    /// Text encoded in hanzi mode once converted to GB 2312
+    Hanzi(String),
+}
+
+/// Check that the input can be encoded, before a version is selected
+pub(crate) fn validate_inputs(inputs: &[Input]) -> Result<(), QRError> {
+    let fnc1 = inputs.iter().any(|input| {
+        matches!(
+            input,
+            Input::Fixed(Mode::FNC1First | Mode::FNC1Second(_), _)
+        )
+    });
+
+    for input in inputs {
+        match input {
+            Input::Fixed(Mode::FNC1First, _) => {}
+            Input::Fixed(Mode::FNC1Second(indicator), _) => match indicator {
+                0..=99 => {}
+                c if (c - 100).is_ascii_alphabetic() => {}
+                _ => {
+                    return Err(QRError::InvalidApplicationIndicator {
+                        application_indicator: *indicator,
+                    })
+                }
+            },
+            Input::Fixed(mode, data) => {
+                segment::validate(mode, data)?;
+                // A GS character is only a separator, encoded as `%`, in FNC1 symbols
+                if let (Mode::Alphanumeric, false) = (mode, fnc1) {
+                    if let Some(index) = data.iter().position(|&c| c == GS) {
+                        return Err(QRError::InvalidCharacter {
+                            index,
+                            mode: Mode::Alphanumeric,
+                        });
+                    }
+                }
+            }
+            Input::Auto(_) | Input::Text(_) => {}
+            Input::Kanji(text) => {
+                shift_jis::encode(text)?;
+            }
+            Input::Hanzi(text) => {
+                gb2312::encode(text)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve the input into the segments to encode for the version
+pub(crate) fn resolve_segments(
+    inputs: &[Input],
+    version: usize,
+) -> Result<Vec<(Mode, Vec<u8>)>, QRError> {
+    let mut segments = vec![];
+    let mut eci = None;
+
+    let fnc1 = inputs.iter().find_map(|input| match input {
+        Input::Fixed(mode @ (Mode::FNC1First | Mode::FNC1Second(_)), _) => Some(mode.clone()),
+        _ => None,
+    });
+
+    for input in inputs {
+        match input {
+            Input::Fixed(Mode::FNC1First | Mode::FNC1Second(_), _) => {}
+            Input::Fixed(mode, data) => {
+                if let Mode::ECI(value) = mode {
+                    eci = Some(*value);
+                }
+                let data = match mode {
+                    Mode::Alphanumeric if fnc1.is_some() => mark_separators(data),
+                    _ => data.clone(),
+                };
+                segments.push((mode.clone(), data));
+            }
+            Input::Auto(data) => {
+                let characters = split_bytes(data, eci == Some(SHIFT_JIS_ECI));
+                segments.extend(segment(&characters, version, fnc1.is_some())?);
+            }
+            Input::Text(text) => {
+                let (charset, data) = encode_text(text, eci);
+                if let Some(value) = charset {
+                    if eci != Some(value) {
+                        segments.push((Mode::ECI(value), vec![]));
+                        eci = Some(value);
+                    }
+                }
+                let characters = split_text(text, &data, charset);
+                segments.extend(segment(&characters, version, fnc1.is_some())?);
+            }
+            Input::Kanji(text) => segments.push((Mode::Kanji, shift_jis::encode(text)?)),
+            Input::Hanzi(text) => segments.push((Mode::Hanzi, gb2312::encode(text)?)),
+        }
+    }
+
+    if let Some(mode) = fnc1 {
+        // The FNC1 mode indicator precedes the first segment that is not an ECI designator
+        let position = segments
+            .iter()
+            .take_while(|(mode, _)| matches!(mode, Mode::ECI(_)))
+            .count();
+        segments.insert(position, (mode, vec![]));
     }
+
+    for (mode, _) in &segments {
+        check_mode(mode, version)?;
+    }
+
+    Ok(utils::optimize_segments(&segments))
+}
+
+/// Check that the mode can be used in the version
+pub(crate) fn check_mode(mode: &Mode, version: usize) -> Result<(), QRError> {
+    if mode_allowed(mode, version) {
+        return Ok(());
+    }
+
+    Err(QRError::ModeNotAllowed {
+        mode: mode.clone(),
+        version: utils::version_from_number(version),
+    })
 }
 
-/// Get the version for the data
+/// Get the smallest version between `min` and `max` that fits the data
+///
+/// Versions are ordered from M1 to M4 and then from 1 to 40. The micro versions are only
+/// tried with `micro`, when a bound is a micro version or for `DetectionOnly`, which allows M1
+/// and falls back to level L in the other versions. A micro version is skipped if it does not
+/// support the error correction level or a mode of the data. If the data only fits in a
+/// version above `max`, the error names `max` and that version as the required version. If no
+/// version up to `max` supports the error correction level, the error names `max`. rMQR
+/// versions are not selected automatically, so they cannot be bounds.
 pub(crate) fn get_version(
-    segments: &Vec<(Mode, Vec<u8>)>,
+    inputs: &[Input],
+    error_correction: &ErrorCorrection,
+    micro: bool,
+    min: Option<&Version>,
+    max: Option<&Version>,
+) -> Result<Version, QRError> {
+    let mut data = BitBuffer::new();
+    select_version(inputs, error_correction, micro, min, max, &mut data)
+}
+
+/// Get the version as `get_version` does, leaving the data encoded in it in the buffer, so
+/// the symbol is built without resolving the segments again
+pub(crate) fn select_version(
+    inputs: &[Input],
     error_correction: &ErrorCorrection,
+    micro: bool,
+    min: Option<&Version>,
+    max: Option<&Version>,
+    data: &mut BitBuffer,
 ) -> Result<Version, QRError> {
-    for i in 1..=40 {
-        let mut data_size = 0;
-        for (mode, data) in segments {
-            let (mode, data) = encode::encode_segment(i, mode, data);
-            data_size += mode.len() + data.len();
+    let min = min.map(utils::version_number).transpose()?;
+    let max = max.map(utils::version_number).transpose()?;
+
+    check_bounds(min, max)?;
+
+    let micro = micro
+        || matches!(error_correction, ErrorCorrection::DetectionOnly)
+        || min.is_some_and(|v| v > 40)
+        || max.is_some_and(|v| v > 40);
+
+    let micro_versions = (41..=44).filter(|_| micro);
+    let min_rank = min.map_or(0, rank);
+    let versions = micro_versions
+        .chain(1..=40)
+        .filter(move |&i| rank(i) >= min_rank);
+
+    if let Some(max) = max {
+        let window = versions.clone().filter(|&i| rank(i) <= rank(max));
+        check_window(window, max, error_correction)?;
+    }
+
+    // The largest version that supports the error correction level and the size of the data
+    // in it, for the error if the data fits in none. Version 40 supports every level.
+    let mut largest = (40, 0);
+
+    for i in versions {
+        let capacity = available_bits(i, error_correction);
+        if capacity == 0 {
+            continue;
+        }
+
+        let size = match encode_inputs(inputs, i, data) {
+            Ok(()) => data.len(),
+            Err(_) if i > 40 => continue,
+            Err(e) => return Err(e),
+        };
+        largest = (i, size);
+
+        if size <= capacity {
+            return match max {
+                Some(max) if rank(i) > rank(max) => Err(QRError::DataTooLarge {
+                    required_bits: size,
+                    available_bits: available_bits(max, error_correction),
+                    version: utils::version_from_number(max),
+                    required_version: Some(utils::version_from_number(i)),
+                }),
+                _ => Ok(utils::version_from_number(i)),
+            };
+        }
+    }
+
+    let (version, size) = largest;
+    Err(QRError::DataTooLarge {
+        required_bits: size,
+        available_bits: available_bits(version, error_correction),
+        version: utils::version_from_number(version),
+        required_version: None,
+    })
+}
+
+/// Get the number of data bits of an automatically selected version at the error correction
+/// level, or 0 if the version does not support it
+fn available_bits(version: usize, error_correction: &ErrorCorrection) -> usize {
+    let level =
+        utils::selected_error_correction(&utils::version_from_number(version), error_correction);
+    utils::get_available_data_size(version, &level).unwrap_or(0) as usize
+}
+
+/// Get the versions from `min` up to `max` in the order M1 to M4, 1 to 40
+///
+/// These are the versions `get_version` selects from with these bounds. Like `get_version`,
+/// this fails if none of them supports the error correction level.
+pub(crate) fn version_window(
+    min: &Version,
+    max: &Version,
+    error_correction: &ErrorCorrection,
+) -> Result<Vec<usize>, QRError> {
+    let min = utils::version_number(min)?;
+    let max = utils::version_number(max)?;
+
+    check_bounds(Some(min), Some(max))?;
+
+    let window: Vec<usize> = (41..=44)
+        .chain(1..=40)
+        .filter(|&v| rank(v) >= rank(min) && rank(v) <= rank(max))
+        .collect();
+
+    check_window(window.iter().copied(), max, error_correction)?;
+
+    Ok(window)
+}
+
+/// Check that the bounds of the automatic version selection are not rMQR versions and that
+/// the minimum is not above the maximum
+fn check_bounds(min: Option<usize>, max: Option<usize>) -> Result<(), QRError> {
+    if min.is_some_and(|v| v > 44) || max.is_some_and(|v| v > 44) {
+        return Err(QRError::InvalidVersionRange {
+            min: utils::version_from_number(min.unwrap_or(1)),
+            max: utils::version_from_number(max.unwrap_or(40)),
+        });
+    }
+
+    if let (Some(min), Some(max)) = (min, max) {
+        if rank(min) > rank(max) {
+            return Err(QRError::InvalidVersionRange {
+                min: utils::version_from_number(min),
+                max: utils::version_from_number(max),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a version of the window supports the error correction level
+///
+/// Otherwise no data fits in the window, and the error names its maximum `max`.
+fn check_window(
+    mut window: impl Iterator<Item = usize>,
+    max: usize,
+    error_correction: &ErrorCorrection,
+) -> Result<(), QRError> {
+    if window.any(|i| available_bits(i, error_correction) > 0) {
+        return Ok(());
+    }
+
+    Err(QRError::ErrorCorrectionNotAllowed {
+        error_correction: error_correction.clone(),
+        version: utils::version_from_number(max),
+    })
+}
+
+/// Get the position of the version in the order M1 to M4, 1 to 40
+fn rank(version: usize) -> usize {
+    match version {
+        41..=44 => version - 41,
+        _ => version + 3,
+    }
+}
+
+/// Get the highest error correction level, starting from the given one, at which the data fits
+/// in the version
+pub(crate) fn boost_error_correction(
+    inputs: &[Input],
+    version: &Version,
+    error_correction: ErrorCorrection,
+) -> ErrorCorrection {
+    let version = match utils::version_number(version) {
+        Ok(version) => version,
+        Err(_) => return error_correction,
+    };
+
+    let data_size = match data_size(inputs, version) {
+        Ok(data_size) => data_size,
+        Err(_) => return error_correction,
+    };
+
+    let mut boosted = error_correction;
+    let levels = [ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H];
+    for level in levels.into_iter().skip(boosted.to_value()) {
+        // rMQR codes have no level Q, so it is skipped on the way to H
+        let capacity = utils::get_available_data_size(version, &level).unwrap_or(0) as usize;
+        if capacity == 0 {
+            continue;
+        }
+        if data_size > capacity {
+            break;
+        }
+        boosted = level;
+    }
+
+    boosted
+}
+
+/// Get the number of bits the data takes in the version
+pub(crate) fn data_size(inputs: &[Input], version: usize) -> Result<usize, QRError> {
+    let mut data = BitBuffer::new();
+    encode_inputs(inputs, version, &mut data)?;
+
+    Ok(data.len())
+}
+
+/// Encode the input in the version into the buffer, replacing the bits it held
+pub(crate) fn encode_inputs(
+    inputs: &[Input],
+    version: usize,
+    data: &mut BitBuffer,
+) -> Result<(), QRError> {
+    data.clear();
+    encode::encode_segments(&resolve_segments(inputs, version)?, version, data)
+}
+
+/// Split the data into the sequence of segments that takes the fewest bits in the version
+///
+/// This is a dynamic program over the characters of the data. For every character it keeps,
+/// per mode, the cheapest encoding of the data so far that ends in a segment of that mode.
+/// Costs are kept in sixths of a bit, so numeric (10 bits per 3 digits) and alphanumeric
+/// (11 bits per 2 characters) characters have integral costs.
+fn segment(
+    characters: &[Character],
+    version: usize,
+    fnc1: bool,
+) -> Result<Vec<(Mode, Vec<u8>)>, QRError> {
+    let allowed = allowed_modes(version);
+
+    let mut header_costs = [usize::MAX; 4];
+    for (i, mode) in MODES.iter().enumerate() {
+        if allowed[i] {
+            let bits = encode::get_mode(mode, version)?.len()
+                + encode::get_bit_count_for_length(version, mode)? as usize;
+            header_costs[i] = bits * 6;
+        }
+    }
+
+    // char_modes[i][m] is the mode of character i when a segment of mode m is open after it,
+    // for the modes m with a cost after character i
+    let mut char_modes: Vec<[usize; 4]> = Vec::with_capacity(characters.len());
+    let mut costs = header_costs;
+
+    for character in characters {
+        let mut current = [usize::MAX; 4];
+        let mut modes = [0; 4];
+
+        // Extend the open segment of each mode with the character
+        for (m, cost) in character_costs(character, fnc1).iter().enumerate() {
+            if let Some(cost) = cost {
+                if costs[m] != usize::MAX {
+                    current[m] = costs[m] + cost;
+                    modes[m] = m;
+                }
+            }
         }
 
-        let capacity = utils::get_available_data_size(i, error_correction);
+        // End a segment after the character and open one of another mode
+        let extended = current;
+        for to in (0..4).filter(|&to| allowed[to]) {
+            for from in (0..4).filter(|&from| extended[from] != usize::MAX) {
+                let cost = extended[from].div_ceil(6) * 6 + header_costs[to];
+                if cost < current[to] {
+                    current[to] = cost;
+                    modes[to] = from;
+                }
+            }
+        }
+
+        char_modes.push(modes);
+        costs = current;
+    }
+
+    let mut mode = match (0..4)
+        .filter(|&m| costs[m] != usize::MAX)
+        .min_by_key(|&m| costs[m])
+    {
+        Some(m) => m,
+        None => {
+            // A character can only be encoded in modes the version does not support
+            let mode = characters
+                .iter()
+                .map(|character| character_costs(character, fnc1))
+                .find(|costs| (0..4).all(|m| !allowed[m] || costs[m].is_none()))
+                .and_then(|costs| (0..4).find(|&m| costs[m].is_some()))
+                .unwrap_or(Mode::Byte.to_value());
+
+            return Err(QRError::ModeNotAllowed {
+                mode: MODES[mode].clone(),
+                version: utils::version_from_number(version),
+            });
+        }
+    };
+
+    // Walk back to find the mode of every character
+    let mut character_modes = vec![0; characters.len()];
+    for i in (0..characters.len()).rev() {
+        mode = char_modes[i][mode];
+        character_modes[i] = mode;
+    }
+
+    let mut segments: Vec<(Mode, Vec<u8>)> = vec![];
+    for (character, &m) in characters.iter().zip(&character_modes) {
+        let bytes = match (&MODES[m], character.kanji) {
+            (Mode::Kanji, Some(code)) => code.to_be_bytes().to_vec(),
+            (Mode::Alphanumeric, _) if fnc1 => escape_fnc1(character.bytes.unwrap_or_default()),
+            _ => character.bytes.unwrap_or_default().to_vec(),
+        };
+
+        match segments.last_mut() {
+            Some((last, data)) if last.to_value() == m => data.extend_from_slice(&bytes),
+            _ => segments.push((MODES[m].clone(), bytes)),
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Encode the text in the smallest character set that represents it
+///
+/// Text of which every non-ASCII character is a Japanese character of JIS X 0208 is encoded
+/// with those characters in kanji mode and needs no ECI designator, unless the ECI designator
+/// in effect is not that of Shift JIS. ASCII text is encoded in the character set of an
+/// ISO-8859-1 or UTF-8 ECI designator in effect, as both hold it. Other text is encoded as
+/// ISO-8859-1 when every character fits in it and as UTF-8 otherwise, returning the ECI
+/// assignment number of the character set.
+fn encode_text(text: &str, eci: Option<usize>) -> (Option<usize>, Vec<u8>) {
+    if matches!(eci, None | Some(SHIFT_JIS_ECI))
+        && text
+            .chars()
+            .all(|c| c.is_ascii() || kanji_code(c).is_some())
+    {
+        (None, text.as_bytes().to_vec())
+    } else if matches!(eci, Some(ISO_8859_1_ECI | UTF_8_ECI)) && text.is_ascii() {
+        (eci, text.as_bytes().to_vec())
+    } else if text.chars().all(|c| (c as u32) <= 0xFF) {
+        (
+            Some(ISO_8859_1_ECI),
+            text.chars().map(|c| c as u8).collect(),
+        )
+    } else {
+        (Some(UTF_8_ECI), text.as_bytes().to_vec())
+    }
+}
+
+/// Split text encoded by `encode_text` into characters
+///
+/// Without a character set only the ASCII characters may be encoded in byte mode, and with
+/// one no character is encoded in kanji mode.
+fn split_text<'a>(text: &str, data: &'a [u8], charset: Option<usize>) -> Vec<Character<'a>> {
+    let mut characters = Vec::with_capacity(data.len());
+    let mut offset = 0;
+
+    for c in text.chars() {
+        let width = match charset {
+            Some(ISO_8859_1_ECI) => 1,
+            _ => c.len_utf8(),
+        };
+
+        characters.push(Character {
+            bytes: (c.is_ascii() || charset.is_some()).then(|| &data[offset..offset + width]),
+            kanji: kanji_code(c).filter(|_| charset.is_none()),
+        });
+        offset += width;
+    }
+
+    characters
+}
+
+/// Get the Shift JIS code of a character that is encoded in kanji mode automatically
+fn kanji_code(c: char) -> Option<u16> {
+    shift_jis::encode_char(c).filter(|&code| shift_jis::is_japanese(code))
+}
+
+/// Split the data into characters, pairing up the bytes of double byte Shift JIS characters
+fn split_bytes(data: &[u8], shift_jis: bool) -> Vec<Character<'_>> {
+    let mut characters = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        if shift_jis && shift_jis::is_lead_byte(data[i]) && i + 1 < data.len() {
+            let pair = &data[i..i + 2];
+            characters.push(Character {
+                bytes: Some(pair),
+                kanji: shift_jis::is_kanji(pair).then(|| u16::from_be_bytes([pair[0], pair[1]])),
+            });
+            i += 2;
+        } else {
+            characters.push(Character {
+                bytes: Some(&data[i..i + 1]),
+                kanji: None,
+            });
+            i += 1;
+        }
+    }
+
+    characters
+}
+
+/// Get the cost in sixths of a bit of a character in each mode, if it can be encoded in it
+///
+/// In FNC1 symbols a GS separator is encoded as `%` in alphanumeric mode, and a literal `%`
+/// takes two characters.
+fn character_costs(character: &Character, fnc1: bool) -> [Option<usize>; 4] {
+    let single = match character.bytes {
+        Some([c]) => Some(*c),
+        _ => None,
+    };
+
+    let alphanumeric = match single {
+        Some(b'%') if fnc1 => Some(66),
+        Some(GS) if fnc1 => Some(33),
+        Some(c) if ALPHANUMERIC.contains(&(c as char)) => Some(33),
+        _ => None,
+    };
+
+    [
+        single.filter(|c| c.is_ascii_digit()).map(|_| 20),
+        alphanumeric,
+        character.bytes.map(|bytes| bytes.len() * 48),
+        character.kanji.map(|_| 78),
+    ]
+}
+
+/// Escape alphanumeric data of an FNC1 symbol that was segmented automatically
+///
+/// GS separators become `%` and literal `%` characters are doubled.
+fn escape_fnc1(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &c in data {
+        match c {
+            GS => escaped.push(b'%'),
+            b'%' => escaped.extend_from_slice(b"%%"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replace the GS separators of fixed alphanumeric data of an FNC1 symbol by `%`
+///
+/// Fixed alphanumeric data is encoded as is otherwise, so a `%` in it is an FNC1 separator and
+/// a literal `%` is written as `%%`.
+fn mark_separators(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .map(|&c| if c == GS { b'%' } else { c })
+        .collect()
+}
+
+/// Check whether the mode can be used in the version
+///
+/// Micro QR codes support neither ECI, FNC1 nor hanzi mode, and rMQR codes do not support
+/// hanzi mode.
+fn mode_allowed(mode: &Mode, version: usize) -> bool {
+    match mode {
+        Mode::Numeric | Mode::Alphanumeric | Mode::Byte | Mode::Kanji => {
+            allowed_modes(version)[mode.to_value()]
+        }
+        Mode::Hanzi => version <= 40,
+        _ => !(41..=44).contains(&version),
+    }
+}
 
-        if data_size <= capacity as usize {
-            return Ok(Version::V(i));
+/// Get the name of the mode for error messages
+pub(crate) fn mode_name(mode: &Mode) -> &'static str {
+    match mode {
+        Mode::Numeric => "Numeric",
+        Mode::Alphanumeric => "Alphanumeric",
+        Mode::Byte => "Byte",
+        Mode::Kanji => "Kanji",
+        Mode::Hanzi => "Hanzi",
+        Mode::ECI(_) => "ECI",
+        Mode::FNC1First | Mode::FNC1Second(_) => "FNC1",
+    }
+}
+
+/// Get the modes that can be used in the version, indexed by `Mode::to_value`
+fn allowed_modes(version: usize) -> [bool; 4] {
+    match version {
+        41 => [true, false, false, false],
+        42 => [true, true, false, false],
+        _ => [true, true, true, true],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolve the inputs for version 1 into the mode names and data of the segments, where
+    /// the data of an ECI designator is its assignment number
+    fn resolve(inputs: &[Input]) -> Vec<(&'static str, Vec<u8>)> {
+        resolve_segments(inputs, 1)
+            .unwrap()
+            .into_iter()
+            .map(|(mode, data)| match mode {
+                Mode::ECI(value) => ("ECI", vec![value as u8]),
+                mode => (mode_name(&mode), data),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn japanese_text_is_encoded_in_kanji_mode() {
+        let segments = resolve(&[Input::Text("東京タワー".into())]);
+
+        assert_eq!(
+            segments,
+            [("Kanji", shift_jis::encode("東京タワー").unwrap())]
+        );
+    }
+
+    #[test]
+    fn greek_cyrillic_and_box_drawing_text_is_encoded_as_utf_8() {
+        for text in ["αβγ", "Жук", "┌─┐"] {
+            let segments = resolve(&[Input::Text(text.into())]);
+
+            assert_eq!(
+                segments,
+                [
+                    ("ECI", vec![UTF_8_ECI as u8]),
+                    ("Byte", text.as_bytes().to_vec())
+                ]
+            );
         }
     }
 
-    Err(QRError::new("Data is too large"))
+    #[test]
+    fn ascii_text_keeps_the_eci_in_effect() {
+        let segments = resolve(&[Input::Text("é€".into()), Input::Text("abc".into())]);
+
+        assert_eq!(
+            segments,
+            [
+                ("ECI", vec![UTF_8_ECI as u8]),
+                ("Byte", "é€abc".as_bytes().to_vec())
+            ]
+        );
+
+        let segments = resolve(&[Input::Text("Köln".into()), Input::Text("abc".into())]);
+
+        assert_eq!(
+            segments,
+            [
+                ("ECI", vec![ISO_8859_1_ECI as u8]),
+                ("Byte", b"K\xF6lnabc".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn kanji_mode_is_not_selected_under_another_eci() {
+        let segments = resolve(&[Input::Text("Köln".into()), Input::Text("東京".into())]);
+
+        assert_eq!(
+            segments,
+            [
+                ("ECI", vec![ISO_8859_1_ECI as u8]),
+                ("Byte", b"K\xF6ln".to_vec()),
+                ("ECI", vec![UTF_8_ECI as u8]),
+                ("Byte", "東京".as_bytes().to_vec())
+            ]
+        );
+
+        let segments = resolve(&[
+            Input::Fixed(Mode::ECI(SHIFT_JIS_ECI), vec![]),
+            Input::Text("東京".into()),
+        ]);
+
+        assert_eq!(
+            segments,
+            [
+                ("ECI", vec![SHIFT_JIS_ECI as u8]),
+                ("Kanji", shift_jis::encode("東京").unwrap())
+            ]
+        );
+    }
+
+    #[test]
+    fn selected_alphanumeric_data_of_fnc1_symbols_is_escaped() {
+        let segments = resolve(&[
+            Input::Fixed(Mode::FNC1First, vec![]),
+            Input::Auto(b"AB%C\x1DDE".to_vec()),
+        ]);
+
+        assert_eq!(
+            segments,
+            [("FNC1", vec![]), ("Alphanumeric", b"AB%%C%DE".to_vec())]
+        );
+    }
+
+    #[test]
+    fn fixed_alphanumeric_data_of_fnc1_symbols_is_encoded_as_is() {
+        let segments = resolve(&[
+            Input::Fixed(Mode::FNC1First, vec![]),
+            Input::Fixed(Mode::Alphanumeric, b"AB%C\x1DDE%%".to_vec()),
+        ]);
+
+        assert_eq!(
+            segments,
+            [("FNC1", vec![]), ("Alphanumeric", b"AB%C%DE%%".to_vec())]
+        );
+
+        let segments = resolve(&[Input::Fixed(Mode::Alphanumeric, b"100%".to_vec())]);
+
+        assert_eq!(segments, [("Alphanumeric", b"100%".to_vec())]);
+    }
+
+    #[test]
+    fn detection_only_allows_m1_and_falls_back_to_level_l() {
+        let version = |data: &[u8], micro| {
+            let inputs = [Input::Auto(data.to_vec())];
+            get_version(&inputs, &ErrorCorrection::DetectionOnly, micro, None, None)
+        };
+
+        assert!(matches!(version(b"12345", false), Ok(Version::M(1))));
+        assert!(matches!(version(b"1234567", false), Ok(Version::M(2))));
+        assert!(matches!(version(&[b'a'; 100], true), Ok(Version::V(5))));
+
+        let fit = crate::QRCode::builder()
+            .add_segment(None, b"1234567")
+            .fit(ErrorCorrection::DetectionOnly)
+            .unwrap();
+        assert!(matches!(fit.version(), Version::M(2)));
+        assert!(matches!(fit.error_correction(), ErrorCorrection::L));
+    }
+
+    #[test]
+    fn window_without_the_error_correction_level_is_rejected() {
+        let version = |error_correction, min, max| {
+            let inputs = [Input::Auto(b"123".to_vec())];
+            get_version(&inputs, &error_correction, false, min, max)
+        };
+
+        assert!(matches!(
+            version(
+                ErrorCorrection::H,
+                Some(&Version::M(1)),
+                Some(&Version::M(4))
+            ),
+            Err(QRError::ErrorCorrectionNotAllowed {
+                error_correction: ErrorCorrection::H,
+                version: Version::M(4)
+            })
+        ));
+        assert!(matches!(
+            version(ErrorCorrection::L, None, Some(&Version::M(1))),
+            Err(QRError::ErrorCorrectionNotAllowed {
+                error_correction: ErrorCorrection::L,
+                version: Version::M(1)
+            })
+        ));
+        assert!(matches!(
+            version(
+                ErrorCorrection::M,
+                Some(&Version::V(2)),
+                Some(&Version::M(4))
+            ),
+            Err(QRError::InvalidVersionRange { .. })
+        ));
+        assert!(matches!(
+            version(ErrorCorrection::M, None, Some(&Version::R(7, 43))),
+            Err(QRError::InvalidVersionRange {
+                min: Version::V(1),
+                max: Version::R(7, 43)
+            })
+        ));
+        assert!(matches!(
+            version(ErrorCorrection::Q, None, Some(&Version::M(4))),
+            Ok(Version::M(4))
+        ));
+
+        assert!(matches!(
+            version_window(&Version::M(1), &Version::M(3), &ErrorCorrection::Q),
+            Err(QRError::ErrorCorrectionNotAllowed {
+                error_correction: ErrorCorrection::Q,
+                version: Version::M(3)
+            })
+        ));
+    }
+
+    #[test]
+    fn data_above_the_maximum_version_names_the_required_version() {
+        let version = |data: &[u8], error_correction, max| {
+            let inputs = [Input::Auto(data.to_vec())];
+            get_version(&inputs, &error_correction, false, None, Some(&max))
+        };
+
+        assert!(matches!(
+            version(b"1234567", ErrorCorrection::DetectionOnly, Version::M(1)),
+            Err(QRError::DataTooLarge {
+                required_bits: 29,
+                available_bits: 20,
+                version: Version::M(1),
+                required_version: Some(Version::M(2))
+            })
+        ));
+
+        // Byte mode is not allowed in M2, so the size is that in M3
+        assert!(matches!(
+            version(b"hello", ErrorCorrection::L, Version::M(2)),
+            Err(QRError::DataTooLarge {
+                required_bits: 46,
+                available_bits: 40,
+                version: Version::M(2),
+                required_version: Some(Version::M(3))
+            })
+        ));
+
+        assert!(matches!(
+            version(&[b'a'; 3000], ErrorCorrection::L, Version::V(40)),
+            Err(QRError::DataTooLarge {
+                required_bits: 24020,
+                available_bits: 23648,
+                version: Version::V(40),
+                required_version: None
+            })
+        ));
+    }
+
+    #[test]
+    fn data_the_version_cannot_encode_names_the_mode() {
+        let error = resolve_segments(&[Input::Auto(b"12a".to_vec())], 42);
+
+        assert!(matches!(
+            error,
+            Err(QRError::ModeNotAllowed {
+                mode: Mode::Byte,
+                version: Version::M(2)
+            })
+        ));
+    }
 }
@@ -1,8 +1,14 @@
-use std::collections::VecDeque;
+use alloc::{vec, vec::Vec};
 
 use crate::{
-    correction, encode, interleave, matrix_builder, matrix_builder_micro,
-    qrcode_builder::QRBuilder, utils, ErrorCorrection, Mode, QRError, Version,
+    bit_buffer::BitBuffer,
+    constants::RMQR_SIZES,
+    correction, encode, interleave,
+    mask::{MaskEvaluator, MaskOptions},
+    matrix_builder, matrix_builder_micro, matrix_builder_rmqr,
+    mode_selector::{self, Input},
+    qrcode_builder::QRBuilder,
+    structured_append, utils, ErrorCorrection, QRError, Version,
 };
 
 #[cfg(feature = "image")]
@@ -13,13 +19,42 @@ use crate::svg;
 
 /// Represents a QR code matrix.
 ///
-/// The QRCode holds a two-dimensional matrix of booleans indicating the QR code
-/// pattern as well as an auxiliary matrix used during the matrix building process.
-/// The `dimension` field represents the width/height of the matrix.
+/// The QRCode holds the modules of the QR code packed into rows of 64 bit words, as well as a
+/// bitmap of the modules of the function patterns, which are set during the matrix building
+/// process and are not masked. The `width` and `height` fields are the size of the matrix,
+/// which is only rectangular for rMQR codes.
 pub struct QRCode {
-    matrix: Vec<bool>,
-    some_matrix: Vec<bool>,
-    dimension: usize,
+    modules: Vec<u64>,
+    function_patterns: Vec<u64>,
+    width: usize,
+    height: usize,
+    row_words: usize,
+    mask: u8,
+    penalty: i32,
+}
+
+/// The buffers of the encoding of a symbol, which are reused for the next symbol
+pub(crate) struct Buffers {
+    /// The encoded segments, which are then terminated and padded to the data codewords
+    pub(crate) data: BitBuffer,
+    /// The data codewords of each block
+    blocks: Vec<Vec<u8>>,
+    /// The error correction codewords of each block
+    ec_blocks: Vec<Vec<u8>>,
+    /// The interleaved codewords and remainder bits, which are placed in the symbol
+    codewords: BitBuffer,
+}
+
+impl Buffers {
+    /// Create empty buffers
+    pub(crate) fn new() -> Buffers {
+        Buffers {
+            data: BitBuffer::new(),
+            blocks: vec![],
+            ec_blocks: vec![],
+            codewords: BitBuffer::new(),
+        }
+    }
 }
 
 impl QRCode {
@@ -28,12 +63,53 @@ impl QRCode {
     /// # Examples
     ///
     /// ```rust
+    /// # use qrforge::QRCode;
     /// let builder = QRCode::builder();
     /// ```
     pub fn builder() -> QRBuilder {
         QRBuilder::new()
     }
 
+    /// Creates a QR code from its modules, row by row, where `true` is dark.
+    ///
+    /// The matrix is taken as is, without function patterns, so every module counts as a data
+    /// module. This is meant for scoring matrices with a `MaskEvaluator`; a square matrix
+    /// smaller than 21 modules is scored as a micro QR code.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::from_modules(21, 21, &[false; 21 * 21])?;
+    /// assert_eq!(qr.try_get(20, 20), Some(false));
+    /// assert!(QRCode::from_modules(21, 21, &[false; 20]).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the matrix is empty or if the number of modules is not the width
+    /// times the height.
+    pub fn from_modules(width: usize, height: usize, modules: &[bool]) -> Result<QRCode, QRError> {
+        if width == 0 || height == 0 || width.checked_mul(height) != Some(modules.len()) {
+            return Err(QRError::InvalidMatrix {
+                modules: modules.len(),
+                width,
+                height,
+            });
+        }
+
+        let mut qr_code = Self::empty(width, height);
+
+        for (i, &dark) in modules.iter().enumerate() {
+            qr_code.set_data(i % width, i / width, dark);
+        }
+
+        Ok(qr_code)
+    }
+
     /// Returns an image builder for the QR code.
     ///
     /// The image builder can be used to create a visual representation (PNG, SVG, etc.)
@@ -53,325 +129,455 @@ impl QRCode {
 
     /// Internal method to build a QR code.
     ///
-    /// This function encodes the segments, applies error correction, interleaves data,
-    /// and then builds the QR matrix using either the standard or micro method based on the version.
-    ///
     /// # Errors
     ///
-    /// Returns a `QRError` if the version is invalid or if there is an error during encoding.
+    /// Returns a `QRError` if the version is invalid, if it does not support the error
+    /// correction level or a mode of the data, or if there is an error during encoding.
     pub(crate) fn build(
         version: Version,
         error_correction: ErrorCorrection,
-        segments: &[(Mode, Vec<u8>)],
+        inputs: &[Input],
+        mask: &MaskOptions,
     ) -> Result<QRCode, QRError> {
-        match version {
-            Version::V(v) => {
-                if !(1..=40).contains(&v) {
-                    return Err(QRError::new("Invalid version"));
-                }
-            }
-            Version::M(v) => {
-                if !(1..=5).contains(&v) {
-                    return Err(QRError::new("Invalid version"));
-                }
-            }
-        }
-
-        let version = match version {
-            Version::V(v) => v,
-            Version::M(v) => v + 40,
-        };
-
-        let dimension = Self::calculate_dimension(version);
-
-        let mut matrix = QRCode {
-            matrix: vec![false; dimension * dimension],
-            some_matrix: vec![false; dimension * dimension],
-            dimension,
-        };
-
-        let mut combined_data = vec![];
+        let version = utils::version_number(&version)?;
+
+        Self::build_on(
+            Self::template(version)?,
+            version,
+            error_correction,
+            inputs,
+            mask,
+        )
+    }
 
-        for (mode, bytes) in segments {
-            let (mode, data) = encode::encode_segment(version, mode, bytes);
-            combined_data.extend_from_slice(&mode);
-            combined_data.extend_from_slice(&data);
-        }
+    /// Internal method to build a QR code on the template of the version.
+    ///
+    /// This function resolves the modes of the input for the version, encodes the segments and
+    /// builds the symbol.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the version does not support the error correction level or a mode
+    /// of the data, or if there is an error during encoding.
+    pub(crate) fn build_on(
+        template: QRCode,
+        version: usize,
+        error_correction: ErrorCorrection,
+        inputs: &[Input],
+        mask: &MaskOptions,
+    ) -> Result<QRCode, QRError> {
+        utils::check_error_correction(version, &error_correction)?;
 
-        let combined_data = encode::build_combined_data(combined_data, version, &error_correction)?;
+        let masks = Self::mask_candidates(version, mask)?;
 
-        let (blocks, ec_blocks) = correction::correction(version, &error_correction, combined_data);
-        let result = interleave::interleave(blocks, ec_blocks, version);
+        let mut buffers = Buffers::new();
+        mode_selector::encode_inputs(inputs, version, &mut buffers.data)?;
 
-        match version {
-            1..=40 => {
-                matrix_builder::build_qr_matrix(&mut matrix, version, &error_correction, result)
-            }
-            41..=44 => matrix_builder_micro::build_qr_matrix(
-                &mut matrix,
-                version,
-                &error_correction,
-                result,
-            ),
-            _ => return Err(QRError::new("Invalid version")),
-        };
+        Self::build_symbol(
+            template,
+            version,
+            &error_correction,
+            &mut buffers,
+            &masks,
+            mask.evaluator,
+        )
+    }
 
-        Ok(matrix)
+    /// Internal method to build a QR code of the data encoded in the buffers on the template of
+    /// the version.
+    ///
+    /// The other buffers hold the codewords of the previous symbol, if any, and are reused.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the version does not support the error correction level or if the
+    /// data does not fit.
+    pub(crate) fn build_encoded(
+        template: QRCode,
+        version: usize,
+        error_correction: ErrorCorrection,
+        buffers: &mut Buffers,
+        mask: &MaskOptions,
+    ) -> Result<QRCode, QRError> {
+        utils::check_error_correction(version, &error_correction)?;
+
+        let masks = Self::mask_candidates(version, mask)?;
+
+        Self::build_symbol(
+            template,
+            version,
+            &error_correction,
+            buffers,
+            &masks,
+            mask.evaluator,
+        )
     }
 
     /// Internal method to build a QR code with structured append.
     ///
-    /// Structured append allows one to encode data across multiple QR codes.
-    /// It splits the data into chunks and adds a header indicating the chunk order.
+    /// Structured append allows one to encode data across up to 16 QR codes. The data is split
+    /// between characters into symbols of the version, and each symbol starts with a header
+    /// holding its position, the number of symbols and the parity of the whole message. With
+    /// `min_symbol_version`, each symbol then uses the smallest version from it that fits its
+    /// part of the data.
     ///
     /// # Errors
     ///
-    /// Returns a `QRError` if the version is invalid or if no segments are provided.
+    /// Returns a `QRError` if the version is invalid, if no segments are provided or if the data
+    /// needs more than 16 symbols.
     pub(crate) fn build_with_structual_append(
         version: Version,
         error_correction: ErrorCorrection,
-        segments: &[(Mode, Vec<u8>)],
+        inputs: &[Input],
+        mask: &MaskOptions,
+        min_symbol_version: Option<usize>,
     ) -> Result<Vec<QRCode>, QRError> {
-        match version {
-            Version::V(v) => {
-                if !(1..=40).contains(&v) {
-                    return Err(QRError::new("Invalid version"));
-                }
-            }
-            Version::M(_) => {
-                return Err(QRError::new(
-                    "Structured append is not supported for micro QR codes",
-                ));
-            }
-        }
+        structured_append::check_version(&version)?;
 
-        let version = match version {
-            Version::V(v) => v,
-            Version::M(v) => v + 40,
-        };
+        let version = utils::version_number(&version)?;
+
+        utils::check_error_correction(version, &error_correction)?;
+
+        let masks = Self::mask_candidates(version, mask)?;
 
-        let dimension = Self::calculate_dimension(version);
+        let segments = mode_selector::resolve_segments(inputs, version)?;
 
         if segments.is_empty() {
-            return Err(QRError::new("No segments provided"));
+            return Err(QRError::NoData);
         }
 
-        let max_size = utils::get_available_data_size(version, &error_correction) as usize - 20;
+        let symbols = structured_append::split(&segments, version, &error_correction)?;
+        let parity = structured_append::parity(&segments);
 
-        let mut chunks = vec![];
-        let mut current_chunk = vec![];
-        let mut current_size = 0;
-        let mut eci = None;
+        let mut qr_codes = vec![];
+        let mut buffers = Buffers::new();
 
-        let mut parity = 0;
+        for (index, segments) in symbols.iter().enumerate() {
+            let version = match min_symbol_version {
+                Some(min) => {
+                    structured_append::symbol_version(segments, min, version, &error_correction)?
+                }
+                None => version,
+            };
 
-        let mut mutable_segments: VecDeque<(Mode, Vec<u8>)> = segments.to_vec().into();
+            buffers.data.clear();
+            buffers
+                .data
+                .append(&structured_append::header(index, symbols.len(), parity));
+            encode::encode_segments(segments, version, &mut buffers.data)?;
 
-        while let Some((mode, data)) = mutable_segments.pop_front() {
-            let (mode_b, data_b) = encode::encode_segment(version, &mode, &data);
+            qr_codes.push(Self::build_symbol(
+                Self::template(version)?,
+                version,
+                &error_correction,
+                &mut buffers,
+                &masks,
+                mask.evaluator,
+            )?);
+        }
 
-            if let Mode::ECI(_) = mode {
-                eci = Some(mode_b);
-                continue;
-            }
+        Ok(qr_codes)
+    }
 
-            let size = mode_b.len() + data_b.len();
+    /// Build the symbol holding the encoded data on the template of the version
+    ///
+    /// This pads the data, applies error correction, interleaves the blocks and then builds
+    /// the QR matrix using the standard, micro or rMQR method based on the version.
+    fn build_symbol(
+        mut matrix: QRCode,
+        version: usize,
+        error_correction: &ErrorCorrection,
+        buffers: &mut Buffers,
+        masks: &[u32],
+        evaluator: &dyn MaskEvaluator,
+    ) -> Result<QRCode, QRError> {
+        encode::build_combined_data(&mut buffers.data, version, error_correction)?;
+
+        correction::correction(
+            version,
+            error_correction,
+            buffers.data.bytes(),
+            &mut buffers.blocks,
+            &mut buffers.ec_blocks,
+        );
+        interleave::interleave(
+            &buffers.blocks,
+            &buffers.ec_blocks,
+            version,
+            &mut buffers.codewords,
+        );
+        let result = &buffers.codewords;
 
-            if current_size + mode_b.len() >= max_size {
-                chunks.push(current_chunk);
-                current_chunk = vec![];
-                if let Some(eci) = eci.clone() {
-                    current_chunk.extend_from_slice(&eci);
-                }
-                current_size = current_chunk.len();
-
-                mutable_segments.insert(0, (mode, data));
-            } else if current_size + size > max_size {
-                // split the data into two chunks if it exceeds the maximum size
-                let mut left_data = vec![];
-                let mut left_size = mode_b.len();
-
-                let mut right_data = vec![];
-                let chunk_size = match mode {
-                    Mode::Kanji => 2,
-                    Mode::Numeric => 3,
-                    Mode::Alphanumeric => 2,
-                    Mode::Byte => 1,
-                    _ => 1,
-                };
-
-                for words in data.chunks(chunk_size) {
-                    let num_of_bits = utils::num_of_bits(&mode, words.len());
-
-                    if left_size + num_of_bits <= max_size {
-                        left_data.extend_from_slice(words);
-                        left_size += num_of_bits;
-                    } else {
-                        right_data.extend_from_slice(words);
-                    }
-                }
+        match version {
+            1..=40 => matrix_builder::build_qr_matrix(
+                &mut matrix,
+                version,
+                error_correction,
+                result,
+                masks,
+                evaluator,
+            )?,
+            41..=44 => matrix_builder_micro::build_qr_matrix(
+                &mut matrix,
+                version,
+                error_correction,
+                result,
+                masks,
+                evaluator,
+            )?,
+            _ => {
+                matrix_builder_rmqr::build_qr_matrix(&mut matrix, version, error_correction, result)
+            }
+        };
 
-                let (left_mode, left_data) = encode::encode_segment(version, &mode, &left_data);
+        Ok(matrix)
+    }
 
-                current_chunk.extend_from_slice(&left_mode);
-                current_chunk.extend_from_slice(&left_data);
+    /// Build the matrix of the version holding its function patterns, on which the symbols of
+    /// the version are built
+    pub(crate) fn template(version: usize) -> Result<QRCode, QRError> {
+        let (width, height) = Self::calculate_size(version);
+        let mut matrix = Self::empty(width, height);
 
-                chunks.push(current_chunk);
+        match version {
+            1..=40 => matrix_builder::add_function_patterns(&mut matrix, version),
+            41..=44 => matrix_builder_micro::add_function_patterns(&mut matrix),
+            45..=76 => matrix_builder_rmqr::add_function_patterns(&mut matrix),
+            _ => {
+                return Err(QRError::InvalidVersion {
+                    version: utils::version_from_number(version),
+                })
+            }
+        };
 
-                current_chunk = vec![];
+        Ok(matrix)
+    }
 
-                if let Some(eci) = eci.clone() {
-                    current_chunk.extend_from_slice(&eci);
-                }
+    /// Create a matrix of light modules without function patterns
+    fn empty(width: usize, height: usize) -> QRCode {
+        let row_words = width.div_ceil(64);
 
-                current_size = current_chunk.len();
+        QRCode {
+            modules: vec![0; row_words * height],
+            function_patterns: vec![0; row_words * height],
+            width,
+            height,
+            row_words,
+            mask: 0,
+            penalty: 0,
+        }
+    }
 
-                mutable_segments.insert(0, (mode, right_data));
-            } else {
-                current_chunk.extend_from_slice(&mode_b);
-                current_chunk.extend_from_slice(&data_b);
+    /// Get the masks to consider for the version, checking the candidates
+    fn mask_candidates(version: usize, mask: &MaskOptions) -> Result<Vec<u32>, QRError> {
+        let count = match version {
+            41..=44 => 4,
+            45..=76 => 1,
+            _ => 8,
+        };
 
-                for byte in utils::bits_to_bytes(&data_b) {
-                    parity ^= byte;
-                }
+        let candidates = match mask.candidates {
+            Some(candidates) => candidates,
+            None => return Ok((0..count).collect()),
+        };
 
-                current_size += size;
-            }
+        if candidates.is_empty() {
+            return Err(QRError::NoMaskCandidates);
         }
 
-        if !current_chunk.is_empty() {
-            chunks.push(current_chunk);
+        if let Some(m) = candidates.iter().find(|&&m| m as u32 >= count) {
+            return Err(QRError::InvalidMask {
+                mask: *m,
+                version: utils::version_from_number(version),
+            });
         }
 
-        let mut qr_codes = vec![];
-
-        for (index, segments) in chunks.iter().enumerate() {
-            let mut matrix = QRCode {
-                matrix: vec![false; dimension * dimension],
-                some_matrix: vec![false; dimension * dimension],
-                dimension,
-            };
+        Ok(candidates.iter().map(|&m| m as u32).collect())
+    }
 
-            let mut combined_data = vec![];
-
-            // add structured append header
-            let mode = [false, false, true, true];
-            let index_bits = [
-                (index >> 3) & 1 == 1,
-                (index >> 2) & 1 == 1,
-                (index >> 1) & 1 == 1,
-                index & 1 == 1,
-            ];
-
-            let total_bits = [
-                (chunks.len() >> 3) & 1 == 1,
-                (chunks.len() >> 2) & 1 == 1,
-                (chunks.len() >> 1) & 1 == 1,
-                chunks.len() & 1 == 1,
-            ];
-
-            let parity_bits = [
-                (parity >> 7) & 1 == 1,
-                (parity >> 6) & 1 == 1,
-                (parity >> 5) & 1 == 1,
-                (parity >> 4) & 1 == 1,
-                (parity >> 3) & 1 == 1,
-                (parity >> 2) & 1 == 1,
-                (parity >> 1) & 1 == 1,
-                parity & 1 == 1,
-            ];
-
-            combined_data.extend_from_slice(&mode);
-            combined_data.extend_from_slice(&index_bits);
-            combined_data.extend_from_slice(&total_bits);
-            combined_data.extend_from_slice(&parity_bits);
-
-            combined_data.extend_from_slice(segments);
-
-            let maybe_combined_data =
-                encode::build_combined_data(combined_data, version, &error_correction);
-
-            let combined_data = match maybe_combined_data {
-                Ok(data) => data,
-                Err(e) => return Err(QRError::new(&format!("{} for chunk {}, split the data into smaller chunks using QRBuilder::add_segment", e, index))),
-            };
+    /// Returns the mask pattern applied to the QR code.
+    ///
+    /// Standard QR codes have masks 0 to 7 and micro QR codes masks 0 to 3. rMQR codes have the
+    /// single mask 0.
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
 
-            let (blocks, ec_blocks) =
-                correction::correction(version, &error_correction, combined_data);
+    /// Returns the penalty of the applied mask, as evaluated by the mask evaluator.
+    ///
+    /// The single mask of rMQR codes is not evaluated, so their penalty is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder().add_segment(None, b"Hello world").build()?;
+    /// println!("mask {} with penalty {}", qr.mask(), qr.penalty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn penalty(&self) -> i32 {
+        self.penalty
+    }
 
-            let result = interleave::interleave(blocks, ec_blocks, version);
+    /// Records the applied mask and its penalty.
+    pub(crate) fn set_mask(&mut self, mask: u32, penalty: i32) {
+        self.mask = mask as u8;
+        self.penalty = penalty;
+    }
 
-            match version {
-                1..=40 => {
-                    matrix_builder::build_qr_matrix(&mut matrix, version, &error_correction, result)
-                }
-                41..=44 => matrix_builder_micro::build_qr_matrix(
-                    &mut matrix,
-                    version,
-                    &error_correction,
-                    result,
-                ),
-                _ => return Err(QRError::new("Invalid version")),
-            };
+    /// Checks if the QR code is a micro QR code.
+    pub(crate) fn is_micro(&self) -> bool {
+        self.width == self.height && self.width < 21
+    }
 
-            qr_codes.push(matrix);
+    /// Retrieves the value of the QR code module at position (x, y).
+    ///
+    /// Returns `Some(true)` if the module is set (black), `Some(false)` if it is not (white)
+    /// and `None` if the position is outside the QR code.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder().add_segment(None, b"Hello world").build()?;
+    /// assert_eq!(qr.try_get(0, 0), Some(true));
+    /// assert_eq!(qr.try_get(7, 7), Some(false));
+    /// assert_eq!(qr.try_get(0, 500), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_get(&self, x: usize, y: usize) -> Option<bool> {
+        if x >= self.width || y >= self.height {
+            return None;
         }
 
-        Ok(qr_codes)
+        Some(self.get(x, y))
     }
 
-    /// Retrieves the value of the QR code module at position (x, y).
+    /// Retrieves the value of the QR code module at position (x, y), which must be inside the
+    /// QR code.
     ///
     /// Returns `true` if the module is set (black), otherwise `false` (white).
     pub(crate) fn get(&self, x: usize, y: usize) -> bool {
-        self.matrix[y * self.dimension + x]
+        (self.modules[y * self.row_words + x / 64] >> (x % 64)) & 1 == 1
     }
 
-    /// Sets the value of the QR code module at position (x, y).
+    /// Sets the value of a module of a function pattern at position (x, y).
     ///
     /// In addition to setting the module's value, this function marks the corresponding
-    /// position in the auxiliary matrix as filled.
+    /// position in the function pattern bitmap, so it is not masked.
     pub(crate) fn set(&mut self, x: usize, y: usize, value: bool) {
-        self.matrix[y * self.dimension + x] = value;
-        self.some_matrix[y * self.dimension + x] = true;
+        let index = y * self.row_words + x / 64;
+        self.function_patterns[index] |= 1 << (x % 64);
+        self.set_data(x, y, value);
     }
 
-    /// Checks if the module at the given position is empty.
+    /// Sets the value of a data module at position (x, y).
+    pub(crate) fn set_data(&mut self, x: usize, y: usize, value: bool) {
+        let index = y * self.row_words + x / 64;
+        match value {
+            true => self.modules[index] |= 1 << (x % 64),
+            false => self.modules[index] &= !(1 << (x % 64)),
+        }
+    }
+
+    /// Checks if the module at the given position is not part of a function pattern.
     pub(crate) fn is_empty(&self, x: usize, y: usize) -> bool {
-        !self.some_matrix[y * self.dimension + x]
+        (self.function_patterns[y * self.row_words + x / 64] >> (x % 64)) & 1 == 0
+    }
+
+    /// Returns the modules of row y, packed into words where bit i of word w is the module in
+    /// column 64 * w + i. The bits past the width are 0.
+    pub(crate) fn row(&self, y: usize) -> &[u64] {
+        &self.modules[y * self.row_words..(y + 1) * self.row_words]
+    }
+
+    /// Inverts the data modules at the positions where the mask condition holds for the row
+    /// and column.
+    ///
+    /// The mask patterns repeat every 12 rows, so the inverted modules are computed for 12 rows
+    /// and applied to the others a word at a time. Applying a mask twice undoes it.
+    pub(crate) fn apply_mask(&mut self, condition: impl Fn(usize, usize) -> bool) {
+        let period = self.height.min(12);
+        let mut pattern = vec![0u64; period * self.row_words];
+        for i in 0..period {
+            for j in 0..self.width {
+                if condition(i, j) {
+                    pattern[i * self.row_words + j / 64] |= 1 << (j % 64);
+                }
+            }
+        }
+
+        for i in 0..self.height {
+            let pattern = &pattern[(i % period) * self.row_words..][..self.row_words];
+            let start = i * self.row_words;
+            for (w, &bits) in pattern.iter().enumerate() {
+                self.modules[start + w] ^= bits & !self.function_patterns[start + w];
+            }
+        }
     }
 
     /// Returns the dimension (width/height) of the QR code.
-    pub(crate) fn dimension(&self) -> usize {
-        self.dimension
+    ///
+    /// rMQR codes are wider than they are high, and this returns their width.
+    pub fn dimension(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the width of the QR code in modules.
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    /// Calculates the dimension of the QR code for the given version.
+    /// Returns the height of the QR code in modules.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"ABC-123")
+    ///     .version(Version::R(7, 59))
+    ///     .build()?;
+    /// assert_eq!((qr.width(), qr.height()), (59, 7));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Calculates the width and height of the QR code for the given version.
     ///
     /// For standard QR codes (versions 1-40), the dimension is calculated as:
     /// (version - 1) * 4 + 21.
-    /// For micro QR codes, a different formula applies.
-    fn calculate_dimension(version: usize) -> usize {
-        if (1..=40).contains(&version) {
-            (version - 1) * 4 + 21
-        } else {
-            (version - 41) * 2 + 11
+    /// For micro QR codes, a different formula applies, and rMQR codes have the size in their
+    /// name.
+    fn calculate_size(version: usize) -> (usize, usize) {
+        match version {
+            1..=40 => ((version - 1) * 4 + 21, (version - 1) * 4 + 21),
+            45..=76 => {
+                let (height, width) = RMQR_SIZES[version - 45];
+                (width, height)
+            }
+            _ => ((version - 41) * 2 + 11, (version - 41) * 2 + 11),
         }
     }
 
     /// Prints the QR code to the console using ASCII characters.
     ///
-    /// Uses "██" for black modules and ("  ") for white modules.
+    /// Uses "██" for black modules and ("  ") for white modules. Requires the `std` feature.
+    #[cfg(feature = "std")]
     pub fn print(&self) {
         let black = "██";
         let white = "  ";
-        for i in 0..self.dimension {
-            for j in 0..self.dimension {
-                if self.is_empty(j, i) {
-                    print!("  ");
-                } else {
-                    print!("{}", if self.get(j, i) { black } else { white });
-                }
+        for i in 0..self.height {
+            for j in 0..self.width {
+                print!("{}", if self.get(j, i) { black } else { white });
             }
             println!();
         }
@@ -381,12 +587,304 @@ impl QRCode {
 
 impl Clone for QRCode {
     fn clone(&self) -> QRCode {
-        let matrix = self.matrix.clone();
-        let some_matrix = self.some_matrix.clone();
         QRCode {
-            matrix,
-            some_matrix,
-            dimension: self.dimension,
+            modules: self.modules.clone(),
+            function_patterns: self.function_patterns.clone(),
+            width: self.width,
+            height: self.height,
+            row_words: self.row_words,
+            mask: self.mask,
+            penalty: self.penalty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    /// Build the symbol of the data in numeric, alphanumeric or byte mode with the mask
+    fn build(
+        version: &Version,
+        error_correction: &ErrorCorrection,
+        data: &[u8],
+        mask: u8,
+    ) -> QRCode {
+        QRCode::builder()
+            .add_segment(None, data)
+            .version(version.clone())
+            .error_correction(error_correction.clone())
+            .mask(mask)
+            .build()
+            .unwrap()
+    }
+
+    /// Check whether the mask condition of ISO/IEC 18004 Table 10 holds for row i, column j
+    ///
+    /// The masks of micro QR codes are the standard masks 1, 4, 6 and 7.
+    fn condition(micro: bool, mask: u8, i: usize, j: usize) -> bool {
+        let mask = match micro {
+            true => [1, 4, 6, 7][mask as usize],
+            false => mask,
+        };
+
+        match mask {
+            0 => (i + j).is_multiple_of(2),
+            1 => i.is_multiple_of(2),
+            2 => j.is_multiple_of(3),
+            3 => (i + j).is_multiple_of(3),
+            4 => (i / 2 + j / 3).is_multiple_of(2),
+            5 => (i * j) % 2 + (i * j) % 3 == 0,
+            6 => ((i * j) % 2 + (i * j) % 3).is_multiple_of(2),
+            _ => ((i + j) % 2 + (i * j) % 3).is_multiple_of(2),
+        }
+    }
+
+    /// Check whether the module holds format information, which depends on the mask
+    fn is_format_information(micro: bool, size: usize, x: usize, y: usize) -> bool {
+        match micro {
+            true => (x == 8 && y <= 8) || (y == 8 && x <= 8),
+            false => (x == 8 && (y <= 8 || y >= size - 8)) || (y == 8 && (x <= 8 || x >= size - 8)),
+        }
+    }
+
+    #[test]
+    fn masks_invert_the_data_modules_where_their_condition_holds() {
+        let symbols: [(Version, ErrorCorrection, &[u8]); 6] = [
+            (Version::V(1), ErrorCorrection::M, b"01234567"),
+            (
+                Version::V(7),
+                ErrorCorrection::L,
+                b"https://example.com/tickets/0001",
+            ),
+            (
+                Version::V(22),
+                ErrorCorrection::Q,
+                b"PALLET 0001 CARTON 0001-0048",
+            ),
+            (
+                Version::V(40),
+                ErrorCorrection::H,
+                b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+            ),
+            (Version::M(2), ErrorCorrection::L, b"ABC12"),
+            (Version::M(4), ErrorCorrection::Q, b"hello"),
+        ];
+
+        for (version, error_correction, data) in &symbols {
+            let template = QRCode::template(utils::version_number(version).unwrap()).unwrap();
+            let micro = template.is_micro();
+            let size = template.width();
+            let unmasked = build(version, error_correction, data, 0);
+
+            for mask in 1..if micro { 4 } else { 8 } {
+                let qr = build(version, error_correction, data, mask);
+                for y in 0..size {
+                    for x in 0..size {
+                        if template.is_empty(x, y) {
+                            let inverted =
+                                condition(micro, mask, y, x) != condition(micro, 0, y, x);
+                            assert_eq!(
+                                qr.get(x, y),
+                                unmasked.get(x, y) != inverted,
+                                "{} mask {} data module {}, {}",
+                                version,
+                                mask,
+                                x,
+                                y
+                            );
+                        } else if !is_format_information(micro, size, x, y) {
+                            assert_eq!(
+                                qr.get(x, y),
+                                unmasked.get(x, y),
+                                "{} mask {} function module {}, {}",
+                                version,
+                                mask,
+                                x,
+                                y
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn function_patterns_are_placed_as_specified() {
+        // The row and column coordinates of the alignment patterns of ISO/IEC 18004 Annex E
+        let symbols: [(usize, &[usize]); 4] = [
+            (1, &[]),
+            (7, &[6, 22, 38]),
+            (22, &[6, 26, 50, 74, 98]),
+            (40, &[6, 30, 58, 86, 114, 142, 170]),
+        ];
+
+        for (version, alignment) in symbols {
+            let qr = build(&Version::V(version), &ErrorCorrection::L, b"1", 0);
+            let size = 17 + 4 * version;
+            assert_eq!(qr.width(), size);
+
+            // Ring distance of the module from the center of a pattern
+            let distance =
+                |x: usize, y: usize, cx: usize, cy: usize| x.abs_diff(cx).max(y.abs_diff(cy));
+
+            // Finder patterns with their light separators: dark at distance 0, 1 and 3
+            for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+                for y in cy.saturating_sub(4)..=(cy + 4).min(size - 1) {
+                    for x in cx.saturating_sub(4)..=(cx + 4).min(size - 1) {
+                        let d = distance(x, y, cx, cy);
+                        assert_eq!(
+                            qr.get(x, y),
+                            d != 2 && d != 4,
+                            "version {} finder module {}, {}",
+                            version,
+                            x,
+                            y
+                        );
+                        assert!(!qr.is_empty(x, y));
+                    }
+                }
+            }
+
+            // Timing patterns between the separators, dark on even coordinates
+            for k in 8..size - 8 {
+                assert_eq!(
+                    qr.get(k, 6),
+                    k.is_multiple_of(2),
+                    "version {} timing {}, 6",
+                    version,
+                    k
+                );
+                assert_eq!(
+                    qr.get(6, k),
+                    k.is_multiple_of(2),
+                    "version {} timing 6, {}",
+                    version,
+                    k
+                );
+            }
+
+            // Dark module above the lower left separator
+            assert!(qr.get(8, size - 8));
+
+            // Alignment patterns, except where they would overlap a finder pattern: dark at
+            // distance 0 and 2
+            let (first, last) = (alignment.first(), alignment.last());
+            for &cy in alignment {
+                for &cx in alignment {
+                    let corner = (Some(&cx), Some(&cy));
+                    if corner == (first, first)
+                        || corner == (first, last)
+                        || corner == (last, first)
+                    {
+                        continue;
+                    }
+                    for y in cy - 2..=cy + 2 {
+                        for x in cx - 2..=cx + 2 {
+                            assert_eq!(
+                                qr.get(x, y),
+                                distance(x, y, cx, cy) != 1,
+                                "version {} alignment module {}, {}",
+                                version,
+                                x,
+                                y
+                            );
+                            assert!(!qr.is_empty(x, y));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read the data modules of a standard QR code in the order of ISO/IEC 18004 7.7.3 and
+    /// unmask them: from the right, in columns of two modules that alternate between upward
+    /// and downward, skipping the vertical timing pattern and the function patterns
+    fn read_data(qr: &QRCode, mask: u8) -> Vec<bool> {
+        let size = qr.width();
+        let mut bits = vec![];
+        let mut right = size - 1;
+        let mut upward = true;
+
+        loop {
+            if right == 6 {
+                right = 5;
+            }
+            for k in 0..size {
+                let y = if upward { size - 1 - k } else { k };
+                for x in [right, right - 1] {
+                    if qr.is_empty(x, y) {
+                        bits.push(qr.get(x, y) != condition(false, mask, y, x));
+                    }
+                }
+            }
+            if right < 2 {
+                return bits;
+            }
+            upward = !upward;
+            right -= 2;
+        }
+    }
+
+    #[test]
+    fn data_modules_read_back_as_the_interleaved_codewords() {
+        let symbols: [(usize, ErrorCorrection, Mode, &[u8], u8); 4] = [
+            (1, ErrorCorrection::M, Mode::Numeric, b"01234567", 0),
+            (
+                7,
+                ErrorCorrection::L,
+                Mode::Byte,
+                b"https://example.com/tickets/0001",
+                3,
+            ),
+            (
+                22,
+                ErrorCorrection::Q,
+                Mode::Alphanumeric,
+                b"PALLET 0001 CARTON 0001-0048",
+                5,
+            ),
+            (
+                40,
+                ErrorCorrection::H,
+                Mode::Alphanumeric,
+                b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+                6,
+            ),
+        ];
+
+        for (version, error_correction, mode, data, mask) in symbols {
+            let qr = QRCode::builder()
+                .add_segment(Some(mode.clone()), data)
+                .version(Version::V(version))
+                .error_correction(error_correction.clone())
+                .mask(mask)
+                .build()
+                .unwrap();
+
+            let (header, bits) = encode::encode_segment(version, &mode, data).unwrap();
+            let mut segment = BitBuffer::new();
+            segment.append(&header);
+            segment.append(&bits);
+            encode::build_combined_data(&mut segment, version, &error_correction).unwrap();
+            let (mut blocks, mut ec_blocks) = (vec![], vec![]);
+            correction::correction(
+                version,
+                &error_correction,
+                segment.bytes(),
+                &mut blocks,
+                &mut ec_blocks,
+            );
+            let mut expected = BitBuffer::new();
+            interleave::interleave(&blocks, &ec_blocks, version, &mut expected);
+
+            let modules = read_data(&qr, mask);
+            assert_eq!(modules.len(), expected.len(), "version {}", version);
+            for (i, &module) in modules.iter().enumerate() {
+                assert_eq!(module, expected.get(i), "version {} bit {}", version, i);
+            }
         }
     }
 }
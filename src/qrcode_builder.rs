@@ -1,4 +1,13 @@
-use crate::{mode_selector, qrcode::QRCode, utils, ErrorCorrection, Mode, QRError, Version};
+use alloc::{boxed::Box, string::ToString, vec, vec::Vec};
+
+use crate::{
+    capacity::Fit,
+    mask::{DefaultMaskEvaluator, MaskEvaluator, MaskOptions},
+    mode_selector::{self, Input},
+    qrcode::QRCode,
+    segment::Segment,
+    structured_append, utils, ErrorCorrection, Mode, QRError, Version,
+};
 
 /// A builder to create a QRCode.
 ///
@@ -11,28 +20,44 @@ use crate::{mode_selector, qrcode::QRCode, utils, ErrorCorrection, Mode, QRError
 /// Creating a QR code using the builder:
 ///
 /// ```rust
+/// # use qrforge::{ErrorCorrection, Mode, QRCode, QRError, Version};
+/// # fn main() -> Result<(), QRError> {
 /// let qr = QRCode::builder()
 ///     .add_segment(Some(Mode::Byte), b"I read the newspaper")
 ///     .add_segment(Some(Mode::Numeric), b"1234567890")
 ///     .error_correction(ErrorCorrection::L)
-///     .version(Version::V(1))
+///     .version(Version::V(2))
 ///     .build()?;
+/// # Ok(())
+/// # }
 /// ```
 ///
 /// Creating a structured append QR code:
 ///
 /// ```rust
+/// # use qrforge::{ErrorCorrection, Mode, QRCode, QRError, Version};
+/// # fn main() -> Result<(), QRError> {
 /// let qr_codes = QRCode::builder()
 ///     .add_segment(Some(Mode::Byte), b"I read the newspaper")
 ///     .add_segment(Some(Mode::Numeric), b"1234567890")
 ///     .error_correction(ErrorCorrection::L)
 ///     .version(Version::V(1))
 ///     .build_with_structual_append()?;
+/// # Ok(())
+/// # }
 /// ```
 pub struct QRBuilder {
     version: Option<Version>,
+    min_version: Option<Version>,
+    max_version: Option<Version>,
     error_correction: Option<ErrorCorrection>,
-    segments: Vec<(Mode, Vec<u8>)>,
+    min_error_correction: Option<ErrorCorrection>,
+    segments: Vec<Input>,
+    prefer_micro: bool,
+    boost_error_correction: bool,
+    minimize_symbol_versions: bool,
+    mask_candidates: Option<Vec<u8>>,
+    mask_evaluator: Option<Box<dyn MaskEvaluator>>,
 }
 
 impl Default for QRBuilder {
@@ -46,86 +71,904 @@ impl QRBuilder {
     pub fn new() -> QRBuilder {
         QRBuilder {
             version: None,
+            min_version: None,
+            max_version: None,
             error_correction: None,
+            min_error_correction: None,
             segments: vec![],
+            prefer_micro: false,
+            boost_error_correction: false,
+            minimize_symbol_versions: false,
+            mask_candidates: None,
+            mask_evaluator: None,
         }
     }
 
     /// Sets the version for the QR code.
     ///
     /// The version determines the size of the QR code. For standard QR codes, valid versions
-    /// are between 1 and 40. For micro QR codes, valid versions are between 1 and 5.
+    /// are between 1 and 40. For micro QR codes, valid versions are between 1 and 4. rMQR codes
+    /// are only built when their version is set, and support error correction levels M and H.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"LOT 2024-0117")
+    ///     .error_correction(ErrorCorrection::H)
+    ///     .version(Version::R(11, 77))
+    ///     .build()?;
+    ///
+    /// let error = QRCode::builder()
+    ///     .add_segment(None, b"LOT")
+    ///     .version(Version::R(7, 27))
+    ///     .build();
+    /// assert!(error.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn version(mut self, version: Version) -> Self {
         self.version = Some(version);
         self
     }
 
+    /// Sets the smallest version to select automatically.
+    ///
+    /// Versions are ordered from M1 to M4 and then from 1 to 40, so a standard version as
+    /// minimum excludes micro QR codes, while a micro version makes them candidates. The
+    /// bound is ignored if an exact version is set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"A-1")
+    ///     .min_version(Version::V(3))
+    ///     .max_version(Version::V(10))
+    ///     .build()?;
+    ///
+    /// let error = QRCode::builder()
+    ///     .add_segment(None, &[b'A'; 100])
+    ///     .max_version(Version::V(2))
+    ///     .build();
+    /// assert!(error.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn min_version(mut self, version: Version) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    /// Sets the largest version to select automatically.
+    ///
+    /// If the data does not fit in it, building fails with a `DataTooLarge` error whose
+    /// `required_version` names the version the data requires. The bound is ignored if an
+    /// exact version is set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Version};
+    /// let error = QRCode::builder()
+    ///     .add_segment(None, &[b'a'; 40])
+    ///     .max_version(Version::V(2))
+    ///     .build();
+    ///
+    /// assert!(matches!(
+    ///     error,
+    ///     Err(QRError::DataTooLarge {
+    ///         version: Version::V(2),
+    ///         required_version: Some(Version::V(3)),
+    ///         ..
+    ///     })
+    /// ));
+    /// ```
+    pub fn max_version(mut self, version: Version) -> Self {
+        self.max_version = Some(version);
+        self
+    }
+
+    /// Sets the minimum error correction level.
+    ///
+    /// The error correction level, M unless it is set, is raised to this level if it is lower,
+    /// and the version is selected for the raised level. The level is only raised further if
+    /// `boost_error_correction` is enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"0123456789")
+    ///     .min_version(Version::V(3))
+    ///     .error_correction(ErrorCorrection::L)
+    ///     .min_error_correction(ErrorCorrection::Q)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn min_error_correction(mut self, ec: ErrorCorrection) -> Self {
+        self.min_error_correction = Some(ec);
+        self
+    }
+
     /// Sets the error correction level.
     ///
     /// The error correction level determines the ability of the QR code to recover data in case
     /// of damage or errors. Higher levels provide better recovery at the cost of reduced data capacity.
+    ///
+    /// Micro QR codes support fewer levels: M1 only supports `DetectionOnly`, which is not
+    /// available in other versions, M2 and M3 support L and M, and M4 supports L, M and Q.
+    /// When the version is selected automatically, `DetectionOnly` allows M1 and falls back to
+    /// level L in the next micro and standard versions if the data does not fit.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"12345")
+    ///     .error_correction(ErrorCorrection::DetectionOnly)
+    ///     .version(Version::M(1))
+    ///     .build()?;
+    ///
+    /// let error = QRCode::builder()
+    ///     .add_segment(None, b"12345")
+    ///     .error_correction(ErrorCorrection::Q)
+    ///     .version(Version::M(2))
+    ///     .build();
+    /// assert!(error.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn error_correction(mut self, ec: ErrorCorrection) -> Self {
         self.error_correction = Some(ec);
         self
     }
 
+    /// Sets whether the error correction level is raised while the data still fits.
+    ///
+    /// Once the version is known, the error correction level is raised from the selected level
+    /// through M, Q and H for as long as the data fits in that version, so spare capacity is
+    /// used for robustness. The level is never lowered. This does not apply to structured
+    /// append.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// // "HELLO" fits in version 1 at every level, so it is encoded at level H
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"HELLO")
+    ///     .version(Version::V(1))
+    ///     .boost_error_correction(true)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn boost_error_correction(mut self, boost: bool) -> Self {
+        self.boost_error_correction = boost;
+        self
+    }
+
+    /// Sets whether micro QR codes are preferred when the version is selected automatically.
+    ///
+    /// The micro versions M1 to M4 are tried before the standard versions, so the smallest
+    /// symbol that fits the data is built. A micro version is only selected if it supports the
+    /// error correction level and the modes of the data: M1 only supports numeric data and
+    /// `ErrorCorrection::DetectionOnly`, M2 adds alphanumeric mode and levels L and M, and M3
+    /// and M4 add byte and kanji mode, with M4 adding level Q. No micro version supports level
+    /// H, ECI, FNC1 or hanzi mode. Micro versions are also tried for `DetectionOnly`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"1234567")
+    ///     .error_correction(ErrorCorrection::L)
+    ///     .prefer_micro(true)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prefer_micro(mut self, prefer_micro: bool) -> Self {
+        self.prefer_micro = prefer_micro;
+        self
+    }
+
+    /// Lets every QR code of a structured append use its own smallest version.
+    ///
+    /// The data is split for the selected version, after which each QR code is built in the
+    /// smallest version, from the minimum version up, that fits its part. Only the last QR code
+    /// usually shrinks. This has no effect on the standard build.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr_codes = QRCode::builder()
+    ///     .add_segment(None, &[b'a'; 30])
+    ///     .version(Version::V(2))
+    ///     .minimize_symbol_versions(true)
+    ///     .build_with_structual_append()?;
+    /// assert_eq!(qr_codes[0].dimension(), 25);
+    /// assert_eq!(qr_codes[1].dimension(), 21);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn minimize_symbol_versions(mut self, minimize: bool) -> Self {
+        self.minimize_symbol_versions = minimize;
+        self
+    }
+
+    /// Forces the mask pattern.
+    ///
+    /// Standard QR codes have masks 0 to 7, micro QR codes masks 0 to 3 and rMQR codes the
+    /// single mask 0; building fails for another mask. The penalty of the mask is still
+    /// evaluated and reported.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder().add_segment(None, b"Hello world").mask(3).build()?;
+    /// assert_eq!(qr.mask(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mask(mut self, mask: u8) -> Self {
+        self.mask_candidates = Some(vec![mask]);
+        self
+    }
+
+    /// Restricts the mask patterns to choose from.
+    ///
+    /// The candidate with the lowest penalty is applied, and ties are broken in favor of the
+    /// lowest mask number.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_segment(None, b"Hello world")
+    ///     .mask_candidates(&[0, 2, 4])
+    ///     .build()?;
+    /// assert!([0, 2, 4].contains(&qr.mask()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mask_candidates(mut self, masks: &[u8]) -> Self {
+        self.mask_candidates = Some(masks.to_vec());
+        self
+    }
+
+    /// Sets the evaluator used to choose the mask pattern.
+    ///
+    /// By default masks are chosen with the `DefaultMaskEvaluator`, which follows ISO/IEC 18004.
+    pub fn mask_evaluator(mut self, evaluator: impl MaskEvaluator + 'static) -> Self {
+        self.mask_evaluator = Some(Box::new(evaluator));
+        self
+    }
+
     /// Adds a data segment to the QR code.
     ///
-    /// If `mode` is provided, the segment is encoded with that mode; otherwise,
-    /// the mode is automatically selected based on the bytes provided.
+    /// If `mode` is provided, the segment is encoded with that mode; otherwise, the bytes are
+    /// split into the sequence of numeric, alphanumeric, byte and kanji segments that takes the
+    /// fewest bits in the selected version. Kanji mode is only considered for Shift JIS data,
+    /// that is when ECI 20 is in effect.
+    ///
+    /// The bytes must be encodable in the mode, as checked by `Segment::new`, otherwise building
+    /// fails. Kanji and hanzi segments consist of Shift JIS and GB 2312 byte pairs.
+    ///
+    /// FNC1 modes are set as with `fnc1_first` and `fnc1_second`, ignoring the bytes.
     pub fn add_segment(mut self, mode: Option<Mode>, bytes: &[u8]) -> Self {
+        match mode {
+            Some(Mode::FNC1First) => return self.fnc1_first(),
+            Some(Mode::FNC1Second(indicator)) => return self.fnc1_second(indicator),
+            _ => {}
+        }
+
         if let Some(m) = mode {
-            self.segments.push((m, bytes.to_vec()));
+            self.segments.push(Input::Fixed(m, bytes.to_vec()));
         } else {
-            self.segments
-                .push((mode_selector::select_mode(bytes), bytes.to_vec()));
+            self.segments.push(Input::Auto(bytes.to_vec()));
         }
         self
     }
 
+    /// Adds a segment to the QR code, which is encoded with its mode.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Segment};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .push_segment(Segment::eci(26)?)
+    ///     .push_segment(Segment::bytes("Größe ".as_bytes()))
+    ///     .push_segment(Segment::numeric("42")?)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn push_segment(mut self, segment: Segment) -> Self {
+        let (mode, data) = segment.into_parts();
+        self.segments.push(Input::Fixed(mode, data));
+        self
+    }
+
+    /// Adds text to the QR code.
+    ///
+    /// The text is split into the segments that take the fewest bits, like `add_segment`
+    /// without a mode. ASCII text is encoded as is, and Japanese text, of which every other
+    /// character is a kana, kanji or symbol of JIS X 0208, is encoded with those characters in
+    /// kanji mode, unless an ECI designator of another character set than Shift JIS is in
+    /// effect. ASCII text keeps an ISO-8859-1 or UTF-8 ECI designator in effect, as both
+    /// character sets hold it. Other text is encoded as ISO-8859-1 when every character fits in
+    /// it and as UTF-8 otherwise, and the ECI designator of that character set is emitted
+    /// before it unless it is already in effect.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_text("Grüße aus Köln")
+    ///     .add_text("東京タワー")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_text(mut self, text: &str) -> Self {
+        self.segments.push(Input::Text(text.to_string()));
+        self
+    }
+
+    /// Adds text as a kanji segment.
+    ///
+    /// The text is converted to Shift JIS using the JIS X 0208 character set. If a character
+    /// is not in it, building fails with an error naming the first such character.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder().add_kanji("点茗").build()?;
+    ///
+    /// let error = QRCode::builder().add_kanji("点A").build();
+    /// assert!(error.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_kanji(mut self, text: &str) -> Self {
+        self.segments.push(Input::Kanji(text.to_string()));
+        self
+    }
+
+    /// Adds text as a hanzi segment.
+    ///
+    /// The text is converted to GB 2312. If a character is not in it, building fails with an
+    /// error naming the first such character. Hanzi mode is defined by GB/T 18284 and is only
+    /// available in standard QR codes; readers that do not implement it cannot decode the
+    /// segment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .add_hanzi("产品编号")
+    ///     .add_text(" A-1234")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_hanzi(mut self, text: &str) -> Self {
+        self.segments.push(Input::Hanzi(text.to_string()));
+        self
+    }
+
     /// Adds an Extended Channel Interpretation (ECI) segment.
     ///
-    /// This method inserts an ECI mode into the segments with the given identifier.
+    /// This method inserts an ECI mode into the segments with the given identifier. Building
+    /// fails if the identifier is above 999999.
     pub fn put_eci(mut self, eci: usize) -> Self {
-        self.segments.push((Mode::ECI(eci), vec![]));
+        self.segments.push(Input::Fixed(Mode::ECI(eci), vec![]));
         self
     }
 
+    /// Marks the data as formatted according to the GS1 General Specifications.
+    ///
+    /// The FNC1 in first position mode indicator is emitted before the first data segment. In
+    /// the data, a GS character (0x1D) represents an FNC1 separator: it is encoded as is in
+    /// byte mode and as `%` in alphanumeric mode, where a literal `%` is encoded as `%%`.
+    /// Segments added with alphanumeric mode are encoded as they are, so a `%` in them is an
+    /// FNC1 separator and a literal `%` is written as `%%`. This replaces any FNC1 mode set
+    /// before.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .fnc1_first()
+    ///     .add_segment(None, b"01040123456789011012AB\x1d3101000123")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fnc1_first(mut self) -> Self {
+        self.set_fnc1(Mode::FNC1First);
+        self
+    }
+
+    /// Marks the data as formatted according to an industry application.
+    ///
+    /// The application indicator is a number from 0 to 99, or the ASCII value of a letter
+    /// plus 100, as assigned by AIM. GS characters in the data represent FNC1 separators as
+    /// with `fnc1_first`. This replaces any FNC1 mode set before.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder()
+    ///     .fnc1_second(37)
+    ///     .add_segment(None, b"AA1234BBB112")
+    ///     .build()?;
+    ///
+    /// let error = QRCode::builder().fnc1_second(150).add_text("AB").build();
+    /// assert!(error.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fnc1_second(mut self, application_indicator: u8) -> Self {
+        self.set_fnc1(Mode::FNC1Second(application_indicator));
+        self
+    }
+
+    /// Replaces the FNC1 mode of the QR code
+    fn set_fnc1(&mut self, mode: Mode) {
+        self.segments.retain(|input| {
+            !matches!(
+                input,
+                Input::Fixed(Mode::FNC1First | Mode::FNC1Second(_), _)
+            )
+        });
+        self.segments.insert(0, Input::Fixed(mode, vec![]));
+    }
+
+    /// Measures how the data fits at the error correction level, without building the QR code.
+    ///
+    /// The version and level are selected as `build` selects them for this level: the level is
+    /// raised to the minimum level, the version is selected within the minimum and maximum
+    /// version, trying micro versions first if they are preferred, unless it is set, and the
+    /// level is then boosted if boosting is enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{ErrorCorrection, QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let builder = QRCode::builder().add_text("https://example.com/order/20240117");
+    ///
+    /// let fit = builder.fit(ErrorCorrection::M)?;
+    /// println!("{} with {} bits left", fit.version(), fit.remaining_bits());
+    ///
+    /// let qr = builder.version(fit.version()).build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the data does not fit or if building would fail for another
+    /// reason known before the symbol is built.
+    pub fn fit(&self, error_correction: ErrorCorrection) -> Result<Fit, QRError> {
+        mode_selector::validate_inputs(&self.segments)?;
+
+        let (version, error_correction) =
+            self.select_version(self.raised_to_minimum(error_correction))?;
+
+        Fit::new(&self.segments, &version, error_correction)
+    }
+
+    /// Measures how the data fits at each of the error correction levels L, M, Q and H.
+    ///
+    /// The levels at which the data does not fit, or which the version does not support, are
+    /// left out, as are levels that are raised or boosted to the level of a previous fit.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let fits = QRCode::builder().add_text("0123456789").fit_levels()?;
+    /// for fit in &fits {
+    ///     println!("level {}: {}", fit.error_correction().to_value(), fit.version());
+    /// }
+    /// assert_eq!(fits.len(), 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if building would fail for a reason other than the size of the data.
+    pub fn fit_levels(&self) -> Result<Vec<Fit>, QRError> {
+        let levels = [
+            ErrorCorrection::L,
+            ErrorCorrection::M,
+            ErrorCorrection::Q,
+            ErrorCorrection::H,
+        ];
+
+        let mut fits = vec![];
+        for level in levels {
+            match self.fit(level) {
+                Ok(fit) => {
+                    let level = fit.error_correction().to_value();
+                    if fits
+                        .iter()
+                        .all(|f: &Fit| f.error_correction().to_value() != level)
+                    {
+                        fits.push(fit);
+                    }
+                }
+                Err(QRError::DataTooLarge { .. } | QRError::ErrorCorrectionNotAllowed { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(fits)
+    }
+
+    /// Get the error correction level, M unless it is set, raised to the minimum level
+    fn error_correction_level(&self) -> ErrorCorrection {
+        self.raised_to_minimum(self.error_correction.clone().unwrap_or(ErrorCorrection::M))
+    }
+
+    /// Raise the error correction level to the minimum level if it is lower
+    fn raised_to_minimum(&self, level: ErrorCorrection) -> ErrorCorrection {
+        // The position of the level in the order from detection only to H
+        let strength = |ec: &ErrorCorrection| match ec {
+            ErrorCorrection::DetectionOnly => 0,
+            _ => ec.to_value() + 1,
+        };
+
+        match &self.min_error_correction {
+            Some(min) if strength(min) > strength(&level) => min.clone(),
+            _ => level,
+        }
+    }
+
+    /// Select the version unless it is set, and the error correction level used in it
+    ///
+    /// The level is the one supported by the selected version, boosted if boosting is enabled.
+    fn select_version(
+        &self,
+        error_correction: ErrorCorrection,
+    ) -> Result<(Version, ErrorCorrection), QRError> {
+        let (version, error_correction) = match &self.version {
+            Some(v) => (v.clone(), error_correction),
+            None => {
+                let version = mode_selector::get_version(
+                    &self.segments,
+                    &error_correction,
+                    self.prefer_micro,
+                    self.min_version.as_ref(),
+                    self.max_version.as_ref(),
+                )?;
+                let error_correction =
+                    utils::selected_error_correction(&version, &error_correction);
+                (version, error_correction)
+            }
+        };
+
+        let error_correction = match self.boost_error_correction {
+            true => {
+                mode_selector::boost_error_correction(&self.segments, &version, error_correction)
+            }
+            false => error_correction,
+        };
+
+        Ok((version, error_correction))
+    }
+
     /// Builds a QR code using the segments and parameters provided.
     ///
-    /// If no version is provided, the version is determined automatically based on the data.
+    /// If no version is provided, the smallest version within the minimum and maximum version
+    /// that fits the data is selected, trying micro versions first if they are preferred. The
+    /// error correction level is then raised if boosting is enabled.
     ///
     /// # Errors
     ///
     /// Returns a `QRError` if an error occurs during the building process.
     pub fn build(self) -> Result<QRCode, QRError> {
-        let error_correction = self.error_correction.unwrap_or(ErrorCorrection::M);
-        let segments = utils::optimize_segments(&self.segments);
+        mode_selector::validate_inputs(&self.segments)?;
 
-        let version = match self.version {
-            Some(v) => v,
-            None => mode_selector::get_version(&segments, &error_correction)?,
+        let (version, error_correction) = self.select_version(self.error_correction_level())?;
+
+        let mask = MaskOptions {
+            candidates: self.mask_candidates.as_deref(),
+            evaluator: self
+                .mask_evaluator
+                .as_deref()
+                .unwrap_or(&DefaultMaskEvaluator),
         };
 
-        QRCode::build(version, error_correction, &segments)
+        QRCode::build(version, error_correction, &self.segments, &mask)
     }
 
     /// Builds QR codes using structured append.
     ///
-    /// This method splits the input data into multiple QR codes.
+    /// This method splits the input data into up to 16 QR codes of the version. Every QR code
+    /// holds its position in the sequence, the number of QR codes and the parity of the data,
+    /// and starts with the ECI designator in effect. Byte mode data is split between the
+    /// characters of the character set of that ECI designator, such as UTF-8, Shift JIS or
+    /// UTF-16, so every QR code holds whole characters.
+    ///
+    /// If no version is provided, the smallest version from the minimum version that needs at
+    /// most 16 QR codes is selected. If a maximum version is set, the version up to it that
+    /// needs the fewest QR codes is selected instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError, Version};
+    /// # fn main() -> Result<(), QRError> {
+    /// let manifest = "PALLET 0001 CARTON 0001-0048\n".repeat(100);
+    ///
+    /// let qr_codes = QRCode::builder()
+    ///     .add_text(&manifest)
+    ///     .build_with_structual_append()?;
+    /// assert!(qr_codes.len() <= 16);
+    ///
+    /// let qr_codes = QRCode::builder()
+    ///     .add_text(&manifest)
+    ///     .max_version(Version::V(20))
+    ///     .build_with_structual_append()?;
+    /// assert!(qr_codes.iter().all(|qr| qr.dimension() <= 97));
+    /// # Ok(())
+    /// # }
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns a `QRError` if the version is not provided or if the building process fails.
+    /// Returns a `QRError` if the data needs more than 16 QR codes of every allowed version or if
+    /// the building process fails.
     pub fn build_with_structual_append(self) -> Result<Vec<QRCode>, QRError> {
-        let error_correction = self.error_correction.unwrap_or(ErrorCorrection::M);
-        let segments = utils::optimize_segments(&self.segments);
+        mode_selector::validate_inputs(&self.segments)?;
+
+        let error_correction = self.error_correction_level();
 
         let version = match self.version {
             Some(v) => v,
-            None => return Err(QRError::new("Version is required for structured append")),
+            None => structured_append::get_version(
+                &self.segments,
+                &error_correction,
+                self.min_version.as_ref(),
+                self.max_version.as_ref(),
+            )?,
         };
 
-        QRCode::build_with_structual_append(version, error_correction, &segments)
+        let min_symbol_version = match self.minimize_symbol_versions {
+            true => match self.min_version.as_ref().map(utils::version_number) {
+                Some(Ok(min)) if min <= 40 => Some(min),
+                _ => Some(1),
+            },
+            false => None,
+        };
+
+        let mask = MaskOptions {
+            candidates: self.mask_candidates.as_deref(),
+            evaluator: self
+                .mask_evaluator
+                .as_deref()
+                .unwrap_or(&DefaultMaskEvaluator),
+        };
+
+        QRCode::build_with_structual_append(
+            version,
+            error_correction,
+            &self.segments,
+            &mask,
+            min_symbol_version,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the modules of the QR code row by row
+    fn modules(qr: &QRCode) -> Vec<bool> {
+        (0..qr.height())
+            .flat_map(|y| (0..qr.width()).map(move |x| qr.get(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn invalid_mask_candidates_are_errors() {
+        let build = |masks: &[u8]| {
+            QRBuilder::new()
+                .add_segment(None, b"12345")
+                .mask_candidates(masks)
+                .build()
+        };
+
+        assert!(matches!(build(&[]), Err(QRError::NoMaskCandidates)));
+        assert!(matches!(
+            build(&[8]),
+            Err(QRError::InvalidMask {
+                mask: 8,
+                version: Version::V(1)
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_input_is_a_typed_error() {
+        let builder = || QRBuilder::new().add_text("A");
+
+        assert!(matches!(
+            builder().put_eci(1000000).build(),
+            Err(QRError::InvalidEci { eci: 1000000 })
+        ));
+        assert!(matches!(
+            builder().fnc1_second(150).build(),
+            Err(QRError::InvalidApplicationIndicator {
+                application_indicator: 150
+            })
+        ));
+        assert!(matches!(
+            builder()
+                .add_segment(Some(Mode::Alphanumeric), b"AB\x1DC")
+                .build(),
+            Err(QRError::InvalidCharacter {
+                index: 2,
+                mode: Mode::Alphanumeric
+            })
+        ));
+        assert!(builder()
+            .fnc1_first()
+            .add_segment(Some(Mode::Alphanumeric), b"AB\x1DC")
+            .build()
+            .is_ok());
+        assert!(matches!(
+            builder().add_kanji("点A").fit(ErrorCorrection::M),
+            Err(QRError::InvalidCharacter {
+                index: 1,
+                mode: Mode::Kanji
+            })
+        ));
+        assert!(matches!(
+            builder().add_hanzi("产A").fit_levels(),
+            Err(QRError::InvalidCharacter {
+                index: 1,
+                mode: Mode::Hanzi
+            })
+        ));
+    }
+
+    #[test]
+    fn minimum_error_correction_level_is_a_floor() {
+        let level = |builder: QRBuilder| builder.error_correction_level().to_value();
+
+        assert_eq!(level(QRBuilder::new()), 1);
+        assert_eq!(
+            level(QRBuilder::new().min_error_correction(ErrorCorrection::L)),
+            1
+        );
+        assert_eq!(
+            level(QRBuilder::new().min_error_correction(ErrorCorrection::Q)),
+            2
+        );
+        assert_eq!(
+            level(
+                QRBuilder::new()
+                    .error_correction(ErrorCorrection::H)
+                    .min_error_correction(ErrorCorrection::Q)
+            ),
+            3
+        );
+        assert_eq!(
+            level(
+                QRBuilder::new()
+                    .error_correction(ErrorCorrection::DetectionOnly)
+                    .min_error_correction(ErrorCorrection::L)
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn fit_agrees_with_build() {
+        let builder = || {
+            QRBuilder::new()
+                .add_text("https://example.com/order/20240117")
+                .min_error_correction(ErrorCorrection::Q)
+        };
+
+        let fit = builder().fit(ErrorCorrection::L).unwrap();
+        assert_eq!(fit.error_correction().to_value(), 2);
+        assert!(matches!(fit.version(), Version::V(3)));
+
+        let built = builder()
+            .error_correction(ErrorCorrection::L)
+            .build()
+            .unwrap();
+        let fixed = QRBuilder::new()
+            .add_text("https://example.com/order/20240117")
+            .version(fit.version())
+            .error_correction(fit.error_correction())
+            .build()
+            .unwrap();
+        assert_eq!(modules(&built), modules(&fixed));
+
+        let boosted = QRBuilder::new()
+            .add_text("HELLO")
+            .min_error_correction(ErrorCorrection::Q)
+            .boost_error_correction(true);
+        let fit = boosted.fit(ErrorCorrection::L).unwrap();
+        assert_eq!(fit.error_correction().to_value(), 3);
+        assert!(matches!(fit.version(), Version::V(1)));
+
+        let levels = builder().fit_levels().unwrap();
+        let levels: Vec<usize> = levels
+            .iter()
+            .map(|f| f.error_correction().to_value())
+            .collect();
+        assert_eq!(levels, [2, 3]);
+    }
+
+    #[test]
+    fn minimum_error_correction_level_does_not_boost() {
+        let build = |builder: QRBuilder| {
+            modules(
+                &builder
+                    .add_segment(None, b"HELLO")
+                    .version(Version::V(1))
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let floor = build(QRBuilder::new().min_error_correction(ErrorCorrection::M));
+        let boosted = build(
+            QRBuilder::new()
+                .min_error_correction(ErrorCorrection::M)
+                .boost_error_correction(true),
+        );
+
+        assert_eq!(
+            floor,
+            build(QRBuilder::new().error_correction(ErrorCorrection::M))
+        );
+        assert_eq!(
+            boosted,
+            build(QRBuilder::new().error_correction(ErrorCorrection::H))
+        );
+        assert_ne!(floor, boosted);
     }
 }
@@ -0,0 +1,193 @@
+use alloc::vec::Vec;
+
+use crate::{
+    constants::ALPHANUMERIC, encode, gb2312, mode_selector, shift_jis, utils, Mode, QRError,
+    Version,
+};
+
+/// Group separator, which marks an FNC1 separator in the data of FNC1 symbols
+const GS: u8 = 0x1D;
+
+/// Largest ECI assignment number
+pub(crate) const MAX_ECI: usize = 999999;
+
+/// A segment of data encoded with a single mode.
+///
+/// The constructors check that the data can be encoded in the mode, so the size of a segment
+/// can be computed before building the QR code.
+///
+/// # Examples
+///
+/// ```rust
+/// # use qrforge::{QRCode, QRError, Segment, Version};
+/// # fn main() -> Result<(), QRError> {
+/// let serial = Segment::numeric("0123456789")?;
+/// let label = Segment::alphanumeric("LOT A-1")?;
+/// assert_eq!(serial.bit_len(Version::V(1))?, 4 + 10 + 34);
+/// assert_eq!(label.bit_len(Version::V(1))?, 4 + 9 + 39);
+///
+/// let qr = QRCode::builder()
+///     .push_segment(serial)
+///     .push_segment(label)
+///     .build()?;
+///
+/// assert!(Segment::numeric("12a4").is_err());
+/// assert!(Segment::alphanumeric("lot").is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Segment {
+    mode: Mode,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    /// Creates a numeric segment of the digits 0 to 9.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` naming the first character that is not a digit.
+    pub fn numeric(digits: &str) -> Result<Segment, QRError> {
+        Self::new(Mode::Numeric, digits.as_bytes())
+    }
+
+    /// Creates an alphanumeric segment of digits, uppercase letters, space and `$%*+-./:`.
+    ///
+    /// In FNC1 symbols, `%` is an FNC1 separator and a literal `%` is written as `%%`. A GS
+    /// character (0x1D) is also accepted there, and is encoded as `%`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` naming the first character that is not in the alphanumeric set.
+    pub fn alphanumeric(text: &str) -> Result<Segment, QRError> {
+        Self::new(Mode::Alphanumeric, text.as_bytes())
+    }
+
+    /// Creates a byte segment.
+    ///
+    /// The bytes are interpreted with the character set of the ECI designator in effect.
+    pub fn bytes(data: &[u8]) -> Segment {
+        Segment {
+            mode: Mode::Byte,
+            data: data.to_vec(),
+        }
+    }
+
+    /// Creates a kanji segment from text, converting it to Shift JIS.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` naming the first character that is not in JIS X 0208.
+    pub fn kanji(text: &str) -> Result<Segment, QRError> {
+        Ok(Segment {
+            mode: Mode::Kanji,
+            data: shift_jis::encode(text)?,
+        })
+    }
+
+    /// Creates a hanzi segment from text, converting it to GB 2312.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` naming the first character that is not in GB 2312.
+    pub fn hanzi(text: &str) -> Result<Segment, QRError> {
+        Ok(Segment {
+            mode: Mode::Hanzi,
+            data: gb2312::encode(text)?,
+        })
+    }
+
+    /// Creates an ECI designator segment, which sets the character set of the byte segments
+    /// after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the assignment number is above 999999.
+    pub fn eci(assignment_number: usize) -> Result<Segment, QRError> {
+        Self::new(Mode::ECI(assignment_number), &[])
+    }
+
+    /// Creates a segment with the mode, checking that the data can be encoded in it.
+    ///
+    /// Kanji and hanzi data are Shift JIS and GB 2312 byte pairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the data cannot be encoded in the mode, or for an FNC1 mode,
+    /// which is set with `QRBuilder::fnc1_first` and `QRBuilder::fnc1_second`.
+    pub fn new(mode: Mode, data: &[u8]) -> Result<Segment, QRError> {
+        validate(&mode, data)?;
+
+        Ok(Segment {
+            mode,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Returns the mode of the segment.
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// Returns the data of the segment, in the encoding of its mode.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of characters of the segment.
+    pub fn char_count(&self) -> usize {
+        match self.mode {
+            Mode::Kanji | Mode::Hanzi => self.data.len() / 2,
+            _ => self.data.len(),
+        }
+    }
+
+    /// Returns the number of bits the segment takes in the version, including its mode
+    /// indicator and character count.
+    ///
+    /// A segment with more characters than the character count indicator of the version holds
+    /// is encoded as several segments, each with its own mode indicator and character count.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `QRError` if the version is invalid or if it does not support the mode.
+    pub fn bit_len(&self, version: Version) -> Result<usize, QRError> {
+        let version = utils::version_number(&version)?;
+
+        mode_selector::check_mode(&self.mode, version)?;
+
+        let (mode, data) = encode::encode_segment(version, &self.mode, &self.data)?;
+        Ok(mode.len() + data.len())
+    }
+
+    /// Returns the mode and data of the segment.
+    pub(crate) fn into_parts(self) -> (Mode, Vec<u8>) {
+        (self.mode, self.data)
+    }
+}
+
+/// Check that the data can be encoded in the mode
+pub(crate) fn validate(mode: &Mode, data: &[u8]) -> Result<(), QRError> {
+    let invalid = match mode {
+        Mode::Numeric => data.iter().position(|c| !c.is_ascii_digit()),
+        Mode::Alphanumeric => data
+            .iter()
+            .position(|&c| c != GS && !ALPHANUMERIC.contains(&(c as char))),
+        Mode::Byte => None,
+        Mode::Kanji => return shift_jis::validate(data),
+        Mode::Hanzi => return gb2312::validate(data),
+        Mode::ECI(value) if *value > MAX_ECI => return Err(QRError::InvalidEci { eci: *value }),
+        Mode::ECI(_) => None,
+        Mode::FNC1First | Mode::FNC1Second(_) => {
+            return Err(QRError::InvalidSegmentMode { mode: mode.clone() })
+        }
+    };
+
+    match invalid {
+        Some(index) => Err(QRError::InvalidCharacter {
+            index,
+            mode: mode.clone(),
+        }),
+        None => Ok(()),
+    }
+}
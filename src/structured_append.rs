@@ -0,0 +1,536 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    bit_buffer::BitBuffer,
+    encode,
+    mode_selector::{self, Input, SHIFT_JIS_ECI, UTF_8_ECI},
+    shift_jis, utils, ErrorCorrection, Mode, QRError, Version,
+};
+
+/// Maximum number of symbols in a structured append sequence
+pub(crate) const MAX_SYMBOLS: usize = 16;
+
+/// The segments of a symbol
+type Symbol = Vec<(Mode, Vec<u8>)>;
+
+/// ECI assignment number of UTF-16 big endian
+const UTF_16BE_ECI: usize = 25;
+
+/// ECI assignment number of Big5
+const BIG5_ECI: usize = 28;
+
+/// ECI assignment number of GB 2312
+const GB2312_ECI: usize = 29;
+
+/// ECI assignment number of EUC-KR
+const EUC_KR_ECI: usize = 30;
+
+/// ECI assignment number of GB 18030
+const GB18030_ECI: usize = 32;
+
+/// ECI assignment number of UTF-16 little endian
+const UTF_16LE_ECI: usize = 33;
+
+/// ECI assignment number of UTF-32 big endian
+const UTF_32BE_ECI: usize = 34;
+
+/// ECI assignment number of UTF-32 little endian
+const UTF_32LE_ECI: usize = 35;
+
+/// Number of bits of the structured append header: the mode indicator, the symbol position,
+/// the total number of symbols and the parity
+const HEADER_BITS: usize = 20;
+
+/// Split the segments into the segments of each symbol of the version
+///
+/// Segments are split between characters where a symbol is full. The characters of byte mode
+/// data are those of the character set of the ECI designator in effect, so a multi byte
+/// character is never split across symbols. Every symbol after the first starts with the ECI
+/// designator in effect, so it can be read on its own.
+///
+/// # Errors
+///
+/// Returns a `QRError` if a character does not fit in an empty symbol or if more than 16 symbols
+/// are needed.
+pub(crate) fn split(
+    segments: &[(Mode, Vec<u8>)],
+    version: usize,
+    error_correction: &ErrorCorrection,
+) -> Result<Vec<Symbol>, QRError> {
+    let capacity = (utils::get_available_data_size(version, error_correction)? as usize)
+        .saturating_sub(HEADER_BITS);
+
+    let mut symbols = vec![];
+    let mut current: Symbol = vec![];
+    let mut used = 0;
+    let mut eci: Option<usize> = None;
+
+    for (mode, data) in segments {
+        if let Mode::ECI(_) | Mode::FNC1First | Mode::FNC1Second(_) = mode {
+            let bits = header_bits(version, mode)?;
+            if used + bits > capacity {
+                // A new ECI designator replaces the one in effect
+                let eci = if let Mode::ECI(_) = mode { None } else { eci };
+                used = next_symbol(&mut symbols, &mut current, eci, version)?;
+            }
+            if let Mode::ECI(value) = mode {
+                eci = Some(*value);
+            }
+            current.push((mode.clone(), vec![]));
+            used += bits;
+            continue;
+        }
+
+        let header = header_bits(version, mode)?;
+
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let space = capacity.saturating_sub(used + header);
+            let mut fit = 0;
+            while fit < rest.len() {
+                let next = fit + character_len(mode, &rest[fit..], eci);
+                if utils::num_of_bits(mode, next) > space {
+                    break;
+                }
+                fit = next;
+            }
+
+            if fit == 0 {
+                if !current.iter().any(|(mode, _)| is_data(mode)) {
+                    return Err(QRError::VersionTooSmall {
+                        version: utils::version_from_number(version),
+                    });
+                }
+
+                used = next_symbol(&mut symbols, &mut current, eci, version)?;
+                continue;
+            }
+
+            current.push((mode.clone(), rest[..fit].to_vec()));
+            used += header + utils::num_of_bits(mode, fit);
+            rest = &rest[fit..];
+        }
+    }
+
+    if current.iter().any(|(mode, _)| is_data(mode)) {
+        symbols.push(current);
+    }
+
+    if symbols.len() > MAX_SYMBOLS {
+        return Err(QRError::TooManySymbols {
+            symbols: symbols.len(),
+            version: utils::version_from_number(version),
+        });
+    }
+
+    Ok(symbols)
+}
+
+/// Close the current symbol and start the next one with the ECI designator in effect
+///
+/// Returns the number of bits used in the next symbol.
+fn next_symbol(
+    symbols: &mut Vec<Symbol>,
+    current: &mut Symbol,
+    eci: Option<usize>,
+    version: usize,
+) -> Result<usize, QRError> {
+    // An ECI designator at the end of the symbol is emitted again in the next one
+    if let Some((Mode::ECI(_), _)) = current.last() {
+        current.pop();
+    }
+
+    symbols.push(core::mem::take(current));
+
+    match eci {
+        Some(eci) => {
+            let mode = Mode::ECI(eci);
+            let bits = header_bits(version, &mode)?;
+            current.push((mode, vec![]));
+            Ok(bits)
+        }
+        None => Ok(0),
+    }
+}
+
+/// Check that the version supports structured append, which only standard QR codes do
+pub(crate) fn check_version(version: &Version) -> Result<(), QRError> {
+    match version {
+        Version::V(_) => Ok(()),
+        _ => Err(QRError::StructuredAppendNotSupported {
+            version: version.clone(),
+        }),
+    }
+}
+
+/// Get the version to split the data in, between `min` and `max`
+///
+/// Without a maximum this is the smallest version that splits the data into at most 16
+/// symbols. With a maximum it is the version up to it that needs the fewest symbols, the
+/// smaller one on a tie. Micro QR codes do not support structured append, so a micro minimum
+/// does not exclude any version.
+///
+/// # Errors
+///
+/// Returns a `QRError` if a bound is invalid or if the data needs more than 16 symbols of every
+/// version.
+pub(crate) fn get_version(
+    inputs: &[Input],
+    error_correction: &ErrorCorrection,
+    min: Option<&Version>,
+    max: Option<&Version>,
+) -> Result<Version, QRError> {
+    if let Some(min @ Version::R(..)) = min {
+        check_version(min)?;
+    }
+
+    if let Some(max) = max {
+        check_version(max)?;
+    }
+
+    let min = match min.map(utils::version_number).transpose()? {
+        Some(min) if min <= 40 => min,
+        _ => 1,
+    };
+
+    let max = max.map(utils::version_number).transpose()?;
+
+    if max.is_some_and(|max| min > max) {
+        return Err(QRError::InvalidVersionRange {
+            min: Version::V(min),
+            max: Version::V(max.unwrap_or(40)),
+        });
+    }
+
+    utils::check_error_correction(min, error_correction)?;
+
+    let mut best: Option<(usize, usize)> = None;
+    let mut error = QRError::NoData;
+
+    for version in min..=max.unwrap_or(40) {
+        let segments = mode_selector::resolve_segments(inputs, version)?;
+
+        if segments.is_empty() {
+            return Err(QRError::NoData);
+        }
+
+        match split(&segments, version, error_correction) {
+            Ok(_) if max.is_none() => return Ok(Version::V(version)),
+            Ok(symbols) => {
+                if best.is_none_or(|(count, _)| symbols.len() < count) {
+                    best = Some((symbols.len(), version));
+                }
+                if symbols.len() == 1 {
+                    break;
+                }
+            }
+            Err(e) => error = e,
+        }
+    }
+
+    match best {
+        Some((_, version)) => Ok(Version::V(version)),
+        None => Err(error),
+    }
+}
+
+/// Get the smallest version from `min` up to `max` that fits the segments of the symbol
+///
+/// The segments are split for `max`, so they fit in it.
+pub(crate) fn symbol_version(
+    segments: &[(Mode, Vec<u8>)],
+    min: usize,
+    max: usize,
+    error_correction: &ErrorCorrection,
+) -> Result<usize, QRError> {
+    for version in min..max {
+        let mut bits = HEADER_BITS;
+        for (mode, data) in segments {
+            bits += header_bits(version, mode)? + utils::num_of_bits(mode, data.len());
+        }
+
+        if bits <= utils::get_available_data_size(version, error_correction)? as usize {
+            return Ok(version);
+        }
+    }
+
+    Ok(max)
+}
+
+/// Compute the parity of the message, which is the XOR of all its bytes
+pub(crate) fn parity(segments: &[(Mode, Vec<u8>)]) -> u8 {
+    segments
+        .iter()
+        .flat_map(|(_, data)| data)
+        .fold(0, |parity, byte| parity ^ byte)
+}
+
+/// Build the structured append header of the symbol at the index
+///
+/// The header holds the mode indicator 0011, the index of the symbol and the total number of
+/// symbols minus one in four bits each, and the parity of the message.
+pub(crate) fn header(index: usize, total: usize, parity: u8) -> BitBuffer {
+    let value = (0b0011 << 16) | (index << 12) | ((total - 1) << 8) | parity as usize;
+
+    let mut header = BitBuffer::with_capacity(HEADER_BITS);
+    header.append_bits(value as u32, HEADER_BITS);
+    header
+}
+
+/// Get the number of bytes of the first character of the data of a segment
+///
+/// Byte mode characters are decoded with the character set of the ECI designator; without
+/// one, or for a single byte character set, every byte is a character.
+fn character_len(mode: &Mode, data: &[u8], eci: Option<usize>) -> usize {
+    let len = match mode {
+        Mode::Kanji | Mode::Hanzi => 2,
+        Mode::Byte => match (eci, data) {
+            (Some(UTF_8_ECI), [lead, ..]) => match lead {
+                0xF0..=0xFF => 4,
+                0xE0..=0xEF => 3,
+                0xC0..=0xDF => 2,
+                _ => 1,
+            },
+            (Some(SHIFT_JIS_ECI), [lead, ..]) if shift_jis::is_lead_byte(*lead) => 2,
+            (Some(BIG5_ECI), [0x81..=0xFE, ..]) => 2,
+            (Some(GB2312_ECI | EUC_KR_ECI), [0xA1..=0xFE, ..]) => 2,
+            (Some(GB18030_ECI), [0x81..=0xFE, 0x30..=0x39, ..]) => 4,
+            (Some(GB18030_ECI), [0x81..=0xFE, ..]) => 2,
+            (Some(UTF_16BE_ECI), [0xD8..=0xDB, ..]) => 4,
+            (Some(UTF_16LE_ECI), [_, 0xD8..=0xDB, ..]) => 4,
+            (Some(UTF_16BE_ECI | UTF_16LE_ECI), _) => 2,
+            (Some(UTF_32BE_ECI | UTF_32LE_ECI), _) => 4,
+            _ => 1,
+        },
+        _ => 1,
+    };
+
+    len.min(data.len())
+}
+
+/// Get the number of bits of the mode indicator and character count of a segment
+fn header_bits(version: usize, mode: &Mode) -> Result<usize, QRError> {
+    Ok(encode::get_mode(mode, version)?.len()
+        + encode::get_bit_count_for_length(version, mode)? as usize)
+}
+
+/// Check whether the mode encodes data
+fn is_data(mode: &Mode) -> bool {
+    !matches!(mode, Mode::ECI(_) | Mode::FNC1First | Mode::FNC1Second(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QRCode;
+
+    #[test]
+    fn structured_append_errors_are_typed() {
+        assert!(matches!(
+            check_version(&Version::M(4)),
+            Err(QRError::StructuredAppendNotSupported {
+                version: Version::M(4)
+            })
+        ));
+        assert!(matches!(
+            check_version(&Version::R(7, 43)),
+            Err(QRError::StructuredAppendNotSupported {
+                version: Version::R(7, 43)
+            })
+        ));
+
+        let error = QRCode::builder()
+            .version(Version::V(1))
+            .build_with_structual_append();
+        assert!(matches!(error, Err(QRError::NoData)));
+
+        let error = QRCode::builder()
+            .add_segment(Some(Mode::Byte), &[b'a'; 1000])
+            .version(Version::V(1))
+            .build_with_structual_append();
+        assert!(matches!(
+            error,
+            Err(QRError::TooManySymbols { symbols, version: Version::V(1) }) if symbols > MAX_SYMBOLS
+        ));
+    }
+
+    #[test]
+    fn header_holds_the_position_the_total_minus_one_and_the_parity() {
+        let bits = |header: BitBuffer| (0..header.len()).map(|i| header.get(i)).collect::<Vec<_>>();
+
+        let expected: Vec<bool> = "0011 0010 0100 10100111"
+            .bytes()
+            .filter(|&b| b != b' ')
+            .map(|b| b == b'1')
+            .collect();
+        assert_eq!(bits(header(2, 5, 0xA7)), expected);
+
+        // The sixteenth of 16 symbols
+        assert_eq!(bits(header(15, 16, 0))[4..12], [true; 8]);
+    }
+
+    #[test]
+    fn parity_covers_the_whole_message() {
+        let message: Vec<u8> = (0..200).map(|i| (i * 7 + 3) as u8).collect();
+        let segments = [
+            (Mode::Numeric, b"0123456789".to_vec()),
+            (Mode::Byte, message.clone()),
+        ];
+
+        let expected = b"0123456789"
+            .iter()
+            .chain(&message)
+            .fold(0, |parity, byte| parity ^ byte);
+        assert_eq!(parity(&segments), expected);
+
+        // The parts combine to the parity of the message, which differs from the parity of a part
+        let symbols = split(&segments, 1, &ErrorCorrection::L).unwrap();
+        assert!(symbols.len() > 1);
+        assert_eq!(
+            symbols
+                .iter()
+                .map(|symbol| parity(symbol))
+                .fold(0, |p, b| p ^ b),
+            expected
+        );
+        assert!(symbols.iter().any(|symbol| parity(symbol) != expected));
+    }
+
+    #[test]
+    fn at_most_sixteen_symbols_are_built() {
+        // A version 1 symbol at level L holds 15 bytes after the structured append header
+        let segments = [(Mode::Byte, vec![b'a'; 16 * 15])];
+        assert_eq!(split(&segments, 1, &ErrorCorrection::L).unwrap().len(), 16);
+
+        let segments = [(Mode::Byte, vec![b'a'; 16 * 15 + 1])];
+        assert!(matches!(
+            split(&segments, 1, &ErrorCorrection::L),
+            Err(QRError::TooManySymbols {
+                symbols: 17,
+                version: Version::V(1)
+            })
+        ));
+    }
+
+    /// Split the data at the ECI designator into symbols of version 1 at level L and get the
+    /// byte mode data of each symbol, checking that it starts with the ECI designator
+    fn split_bytes(eci: usize, data: &[u8]) -> Vec<Vec<u8>> {
+        let segments = [(Mode::ECI(eci), vec![]), (Mode::Byte, data.to_vec())];
+        let symbols = split(&segments, 1, &ErrorCorrection::L).unwrap();
+        assert!(symbols.len() > 1);
+
+        symbols
+            .iter()
+            .map(|symbol| {
+                assert!(matches!(symbol[0], (Mode::ECI(value), _) if value == eci));
+                symbol.iter().flat_map(|(_, data)| data.clone()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn characters_have_the_length_of_their_character_set() {
+        assert_eq!(
+            character_len(&Mode::Byte, "é".as_bytes(), Some(UTF_8_ECI)),
+            2
+        );
+        assert_eq!(
+            character_len(&Mode::Byte, "€".as_bytes(), Some(UTF_8_ECI)),
+            3
+        );
+        assert_eq!(
+            character_len(&Mode::Byte, "😀".as_bytes(), Some(UTF_8_ECI)),
+            4
+        );
+        assert_eq!(character_len(&Mode::Byte, b"a\xE2", Some(UTF_8_ECI)), 1);
+        assert_eq!(
+            character_len(&Mode::Byte, &[0x82, 0xA0], Some(SHIFT_JIS_ECI)),
+            2
+        );
+        assert_eq!(character_len(&Mode::Byte, &[0xB1], Some(SHIFT_JIS_ECI)), 1);
+        assert_eq!(
+            character_len(&Mode::Byte, &[0xB0, 0xA1], Some(GB2312_ECI)),
+            2
+        );
+        assert_eq!(character_len(&Mode::Byte, b"ab", Some(GB2312_ECI)), 1);
+        assert_eq!(character_len(&Mode::Byte, "€".as_bytes(), None), 1);
+
+        // A truncated character ends with the data
+        assert_eq!(
+            character_len(&Mode::Byte, &[0xE2, 0x82], Some(UTF_8_ECI)),
+            2
+        );
+    }
+
+    #[test]
+    fn utf_8_characters_are_not_split() {
+        let text = ["a", &"€".repeat(40)].concat();
+        let symbols = split_bytes(UTF_8_ECI, text.as_bytes());
+
+        for data in &symbols {
+            assert!(core::str::from_utf8(data).is_ok());
+        }
+        assert_eq!(symbols.concat(), text.as_bytes());
+    }
+
+    #[test]
+    fn symbols_broken_before_a_header_start_with_the_eci_in_effect() {
+        // The ECI designator and the 31 digits leave 2 of the 132 bits of version 1 at level L,
+        // so the symbol is broken before the FNC1 mode indicator
+        let text = "€".repeat(20);
+        let segments = [
+            (Mode::ECI(UTF_8_ECI), vec![]),
+            (Mode::Numeric, [b'7'; 31].to_vec()),
+            (Mode::FNC1First, vec![]),
+            (Mode::Byte, text.as_bytes().to_vec()),
+        ];
+        let symbols = split(&segments, 1, &ErrorCorrection::L).unwrap();
+
+        assert!(symbols.len() > 2);
+        for symbol in &symbols {
+            assert!(matches!(symbol[0], (Mode::ECI(UTF_8_ECI), _)));
+            assert!(symbol.iter().any(|(mode, _)| is_data(mode)));
+        }
+        assert!(matches!(symbols[1][1], (Mode::FNC1First, _)));
+
+        let bytes: Vec<u8> = symbols
+            .iter()
+            .flatten()
+            .filter(|(mode, _)| matches!(mode, Mode::Byte))
+            .flat_map(|(_, data)| data.clone())
+            .collect();
+        assert_eq!(bytes, text.as_bytes());
+    }
+
+    #[test]
+    fn shift_jis_characters_are_not_split() {
+        // A single byte katakana shifts the pairs of hiragana あ to odd positions
+        let mut data = vec![0xB1];
+        for _ in 0..40 {
+            data.extend_from_slice(&[0x82, 0xA0]);
+        }
+        let symbols = split_bytes(SHIFT_JIS_ECI, &data);
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            let pairs = if i == 0 { &symbol[1..] } else { &symbol[..] };
+            assert!(pairs.chunks(2).all(|pair| pair == [0x82, 0xA0]));
+        }
+        assert_eq!(symbols.concat(), data);
+    }
+
+    #[test]
+    fn gb_2312_characters_are_not_split() {
+        // An ASCII character shifts the pairs of 啊 to odd positions
+        let mut data = vec![b'a'];
+        for _ in 0..40 {
+            data.extend_from_slice(&[0xB0, 0xA1]);
+        }
+        let symbols = split_bytes(GB2312_ECI, &data);
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            let pairs = if i == 0 { &symbol[1..] } else { &symbol[..] };
+            assert!(pairs.chunks(2).all(|pair| pair == [0xB0, 0xA1]));
+        }
+        assert_eq!(symbols.concat(), data);
+    }
+}
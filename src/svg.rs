@@ -1,4 +1,7 @@
 #![cfg(feature = "svg")]
+use alloc::{format, vec::Vec};
+
+#[cfg(feature = "std")]
 use std::{fs::File, io::Write};
 
 use crate::{color::Color, enums::ErrorEnum, error::QRError, qrcode::QRCode};
@@ -9,16 +12,17 @@ use crate::{color::Color, enums::ErrorEnum, error::QRError, qrcode::QRCode};
 ///
 /// Generate an SVG from a QRCode and save it:
 ///
-/// ```rust
-/// # use qr_module::{QRCode, Mode, ErrorCorrection};
+/// ```rust,no_run
+/// # use qrforge::{ErrorCorrection, Mode, QRCode, Version};
 /// let qr = QRCode::builder()
 ///     .add_segment(Some(Mode::Byte), b"https://example.com")
 ///     .error_correction(ErrorCorrection::L)
-///     .version(1.into())
+///     .version(Version::V(1))
 ///     .build()
 ///     .unwrap();
 ///
-/// qr.image_builder()  // you can either add a svg method or use SvgQRCode::new
+/// # #[cfg(feature = "std")]
+/// qr.svg_builder()
 ///     .set_border(10)
 ///     .set_width(300)
 ///     .set_height(300)
@@ -39,11 +43,11 @@ pub struct SvgQRCode {
 impl SvgQRCode {
     /// Creates a new SvgQRCode with default parameters based on the QR code's dimensions.
     pub(crate) fn new(qr_code: QRCode) -> Self {
-        let dimension = qr_code.dimension();
+        let (width, height) = (qr_code.width(), qr_code.height());
         SvgQRCode {
             qr_code,
-            width: dimension,
-            height: dimension,
+            width,
+            height,
             border: 0,
             border_color: Color::new(255, 255, 255, 255),
             dark_color: Color::new(0, 0, 0, 255),
@@ -56,10 +60,26 @@ impl SvgQRCode {
     ///
     /// If the provided border is too large such that the drawable area is smaller than
     /// the QR code dimension, an error is recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use qrforge::{QRCode, QRError};
+    /// # fn main() -> Result<(), QRError> {
+    /// let qr = QRCode::builder().add_segment(None, b"Hello world").build()?;
+    ///
+    /// let svg = qr
+    ///     .svg_builder()
+    ///     .set_width(100)
+    ///     .set_height(100)
+    ///     .set_border(60)
+    ///     .build_svg_bytes();
+    /// assert!(svg.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn set_border(&mut self, border: usize) -> &mut Self {
-        if self.width - 2 * border < self.qr_code.dimension()
-            || self.height - 2 * border < self.qr_code.dimension()
-        {
+        if !self.fits(border) {
             self.error.push(ErrorEnum::Border);
         } else {
             self.error.retain(|e| *e != ErrorEnum::Border);
@@ -68,11 +88,23 @@ impl SvgQRCode {
         self
     }
 
+    /// Checks if the QR code with the border on both sides fits in the width and height.
+    fn fits(&self, border: usize) -> bool {
+        let fits = |size: usize, modules: usize| {
+            border
+                .checked_mul(2)
+                .and_then(|border| border.checked_add(modules))
+                .is_some_and(|needed| needed <= size)
+        };
+
+        fits(self.width, self.qr_code.width()) && fits(self.height, self.qr_code.height())
+    }
+
     /// Sets the image width.
     ///
-    /// The width must be at least as large as the QR code dimension.
+    /// The width must be at least as large as the QR code width.
     pub fn set_width(&mut self, width: usize) -> &mut Self {
-        if width < self.qr_code.dimension() {
+        if width < self.qr_code.width() {
             self.error.push(ErrorEnum::Width);
         } else {
             self.error.retain(|e| *e != ErrorEnum::Width);
@@ -83,9 +115,9 @@ impl SvgQRCode {
 
     /// Sets the image height.
     ///
-    /// The height must be at least as large as the QR code dimension.
+    /// The height must be at least as large as the QR code height.
     pub fn set_height(&mut self, height: usize) -> &mut Self {
-        if height < self.qr_code.dimension() {
+        if height < self.qr_code.height() {
             self.error.push(ErrorEnum::Height);
         } else {
             self.error.retain(|e| *e != ErrorEnum::Height);
@@ -118,16 +150,25 @@ impl SvgQRCode {
     ///
     /// Returns an error if any of the parameters are invalid.
     pub fn build_svg_bytes(&self) -> Result<Vec<u8>, QRError> {
-        if !self.error.is_empty() {
-            return Err(QRError::new("Invalid parameters"));
+        if let Some(e) = self.error.first() {
+            return Err(QRError::RenderParameter {
+                parameter: e.name(),
+            });
+        }
+
+        // The width or height may have been reduced after the border was set
+        if !self.fits(self.border) {
+            return Err(QRError::RenderParameter {
+                parameter: ErrorEnum::Border.name(),
+            });
         }
 
-        let pixel_size_width = (self.width - 2 * self.border) / self.qr_code.dimension();
-        let pixel_size_height = (self.height - 2 * self.border) / self.qr_code.dimension();
-        let pixel_size = std::cmp::min(pixel_size_width, pixel_size_height);
+        let pixel_size_width = (self.width - 2 * self.border) / self.qr_code.width();
+        let pixel_size_height = (self.height - 2 * self.border) / self.qr_code.height();
+        let pixel_size = core::cmp::min(pixel_size_width, pixel_size_height);
 
-        let border_width = (self.width - self.qr_code.dimension() * pixel_size) / 2;
-        let border_height = (self.height - self.qr_code.dimension() * pixel_size) / 2;
+        let border_width = (self.width - self.qr_code.width() * pixel_size) / 2;
+        let border_height = (self.height - self.qr_code.height() * pixel_size) / 2;
 
         let mut svg = Vec::new();
         svg.push(b'<');
@@ -157,8 +198,8 @@ impl SvgQRCode {
         svg.extend_from_slice(b"\" />\n");
 
         // Add QR code modules.
-        for y in 0..self.qr_code.dimension() {
-            for x in 0..self.qr_code.dimension() {
+        for y in 0..self.qr_code.height() {
+            for x in 0..self.qr_code.width() {
                 if self.qr_code.get(x, y) {
                     svg.push(b'<');
                     svg.extend_from_slice(b"rect ");
@@ -193,12 +234,12 @@ impl SvgQRCode {
 
     /// Builds the SVG and writes it to a file at the given path.
     ///
-    /// Returns an error if the file cannot be created or written to.
+    /// Returns an error if the file cannot be created or written to. Requires the `std` feature.
+    #[cfg(feature = "std")]
     pub fn build_svg_file(&self, path: &str) -> Result<(), QRError> {
         let svg_data = self.build_svg_bytes()?;
-        let mut file = File::create(path).map_err(|e| QRError::new(&e.to_string()))?;
-        file.write_all(&svg_data)
-            .map_err(|e| QRError::new(&e.to_string()))?;
+        let mut file = File::create(path)?;
+        file.write_all(&svg_data)?;
         Ok(())
     }
 }
@@ -1,77 +1,168 @@
-use crate::{constants::DATA_CODEWORDS, ErrorCorrection, Mode};
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    constants::{DATA_CODEWORDS, RMQR_SIZES},
+    ErrorCorrection, Mode, QRError, Version,
+};
+
+/// Get the number of data bits for a given version and error correction level
+///
+/// Returns 0 if the version does not support the error correction level.
+pub(crate) fn get_available_data_size(
+    version: usize,
+    error_correction: &ErrorCorrection,
+) -> Result<u32, QRError> {
+    if !(1..=76).contains(&version) {
+        return Err(QRError::InvalidVersion {
+            version: version_from_number(version),
+        });
+    }
 
-/// Get the number of data codewords for a given version and error correction level
-pub(crate) fn get_available_data_size(version: usize, error_correction: &ErrorCorrection) -> u32 {
-    if version > 44 {
-        panic!("Invalid version");
+    if check_error_correction(version, error_correction).is_err() {
+        return Ok(0);
     }
 
-    DATA_CODEWORDS[version - 1][error_correction.to_value()]
+    Ok(DATA_CODEWORDS[version - 1][ec_index(error_correction)])
+}
+
+/// Get the index of the error correction level in the tables
+///
+/// The tables hold the detection only level of M1 in the place of level L.
+pub(crate) fn ec_index(error_correction: &ErrorCorrection) -> usize {
+    match error_correction {
+        ErrorCorrection::DetectionOnly => 0,
+        _ => error_correction.to_value(),
+    }
 }
 
-/// Convert bits to bytes
-pub(crate) fn bits_to_bytes(data: &[bool]) -> Vec<u8> {
-    let mut bytes = vec![];
-    let mut byte = 0;
+/// Check that the version supports the error correction level
+///
+/// M1 only supports error detection, M2 and M3 levels L and M, M4 levels L, M and Q, and the
+/// rMQR versions levels M and H.
+pub(crate) fn check_error_correction(
+    version: usize,
+    error_correction: &ErrorCorrection,
+) -> Result<(), QRError> {
+    if !(1..=76).contains(&version) {
+        return Err(QRError::InvalidVersion {
+            version: version_from_number(version),
+        });
+    }
 
-    for (i, bit) in data.iter().enumerate() {
-        byte |= (*bit as u8) << (7 - i % 8);
+    let supported = match (version, error_correction) {
+        (41, ErrorCorrection::DetectionOnly) => true,
+        (41, _) | (_, ErrorCorrection::DetectionOnly) => false,
+        _ => DATA_CODEWORDS[version - 1][error_correction.to_value()] != 0,
+    };
 
-        if i % 8 == 7 {
-            bytes.push(byte);
-            byte = 0;
-        }
+    if supported {
+        return Ok(());
+    }
+
+    Err(QRError::ErrorCorrectionNotAllowed {
+        error_correction: error_correction.clone(),
+        version: version_from_number(version),
+    })
+}
+
+/// Get the error correction level of an automatically selected version
+///
+/// `DetectionOnly` allows M1, the only version that supports it, so the other versions are
+/// selected and built at level L instead.
+pub(crate) fn selected_error_correction(
+    version: &Version,
+    error_correction: &ErrorCorrection,
+) -> ErrorCorrection {
+    match (version, error_correction) {
+        (Version::M(1), _) => error_correction.clone(),
+        (_, ErrorCorrection::DetectionOnly) => ErrorCorrection::L,
+        _ => error_correction.clone(),
+    }
+}
+
+/// Get the number of the version, where micro versions M1 to M4 are numbered 41 to 44 and the
+/// rMQR versions R7x43 to R17x139 45 to 76
+pub(crate) fn version_number(version: &Version) -> Result<usize, QRError> {
+    match *version {
+        Version::V(v) if (1..=40).contains(&v) => Ok(v),
+        Version::V(v) => Err(QRError::InvalidVersion {
+            version: Version::V(v),
+        }),
+        Version::M(v) if (1..=4).contains(&v) => Ok(v + 40),
+        Version::M(v) => Err(QRError::InvalidVersion {
+            version: Version::M(v),
+        }),
+        Version::R(height, width) => match RMQR_SIZES.iter().position(|&s| s == (height, width)) {
+            Some(i) => Ok(i + 45),
+            None => Err(QRError::InvalidVersion {
+                version: Version::R(height, width),
+            }),
+        },
     }
+}
 
-    bytes
+/// Get the version with the number
+pub(crate) fn version_from_number(version: usize) -> Version {
+    match version {
+        41..=44 => Version::M(version - 40),
+        45..=76 => {
+            let (height, width) = RMQR_SIZES[version - 45];
+            Version::R(height, width)
+        }
+        _ => Version::V(version),
+    }
 }
 
 /// calculate the number of bits it takes to encode the data
 pub(crate) fn num_of_bits(mode: &Mode, bytes: usize) -> usize {
     match mode {
-        Mode::Numeric => match bytes % 3 {
-            0 => bytes / 3 * 10,
-            1 => (bytes / 3) * 10 + 4,
-            2 => (bytes / 3) * 10 + 7,
-            _ => panic!("Invalid number of bytes"),
-        },
+        // The remaining one or two digits take 4 or 7 bits
+        Mode::Numeric => bytes / 3 * 10 + [0, 4, 7][bytes % 3],
 
-        Mode::Alphanumeric => match bytes % 2 {
-            0 => bytes / 2 * 11,
-            1 => (bytes / 2) * 11 + 6,
-            _ => panic!("Invalid number of bytes"),
-        },
+        // A remaining character takes 6 bits
+        Mode::Alphanumeric => bytes / 2 * 11 + bytes % 2 * 6,
 
         Mode::Byte => bytes * 8,
 
-        Mode::Kanji => bytes * 13,
+        Mode::Kanji | Mode::Hanzi => bytes / 2 * 13,
 
-        Mode::ECI(_) => 0,
+        Mode::ECI(_) | Mode::FNC1First | Mode::FNC1Second(_) => 0,
     }
 }
 
-/// Optimize the segments, combining segments of the same mode
-pub(crate) fn optimize_segments(segments: &Vec<(Mode, Vec<u8>)>) -> Vec<(Mode, Vec<u8>)> {
-    // combine segments of the same mode
-    let mut optimized_segments = Vec::new();
-    let mut current_mode = Mode::Byte;
-    let mut current_data = Vec::new();
+/// Optimize the segments, combining adjacent segments of the same mode
+///
+/// Empty data segments are dropped, and an ECI segment that is directly followed by another
+/// one is replaced by it, as it would have no effect.
+pub(crate) fn optimize_segments(segments: &[(Mode, Vec<u8>)]) -> Vec<(Mode, Vec<u8>)> {
+    let mut optimized_segments: Vec<(Mode, Vec<u8>)> = Vec::new();
 
     for (mode, data) in segments {
-        if mode.to_value() == current_mode.to_value() {
-            current_data.extend_from_slice(data);
-        } else {
-            if !current_data.is_empty() {
-                optimized_segments.push((current_mode, current_data));
+        match mode {
+            Mode::ECI(_) => {
+                if let Some((Mode::ECI(_), _)) = optimized_segments.last() {
+                    optimized_segments.pop();
+                }
+                optimized_segments.push((mode.clone(), vec![]));
+                continue;
+            }
+            Mode::FNC1First | Mode::FNC1Second(_) => {
+                optimized_segments.push((mode.clone(), vec![]));
+                continue;
             }
+            _ => {}
+        }
 
-            current_mode = mode.clone();
-            current_data = data.clone();
+        if data.is_empty() {
+            continue;
         }
-    }
 
-    if !current_data.is_empty() {
-        optimized_segments.push((current_mode, current_data));
+        match optimized_segments.last_mut() {
+            Some((last, last_data)) if last.to_value() == mode.to_value() => {
+                last_data.extend_from_slice(data);
+            }
+            _ => optimized_segments.push((mode.clone(), data.clone())),
+        }
     }
 
     optimized_segments